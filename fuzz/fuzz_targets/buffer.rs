@@ -0,0 +1,24 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use drogue_esp8266::fuzzing::{split, IngressBuffer};
+use drogue_esp8266::protocol::Response;
+
+// The first octet selects the size of the chunks in which the remaining
+// octets are written, mimicking the ISR and digest loop interleaving.
+fuzz_target!(|data: &[u8]| {
+    if let Some((chunk_len, data)) = data.split_first() {
+        let mut buffer = IngressBuffer::new();
+        let (mut writer, mut reader) = split(&mut buffer);
+        for chunk in data.chunks(*chunk_len as usize % 64 + 1) {
+            for octet in chunk.iter() {
+                let _ = writer.write(*octet);
+            }
+            while let Ok(response) = reader.parse() {
+                if let Response::None = response {
+                    break;
+                }
+            }
+        }
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use drogue_esp8266::fuzzing::parse;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse(data);
+});
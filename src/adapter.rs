@@ -1,21 +1,21 @@
-use embedded_hal::{digital::v2::OutputPin, serial::Read, serial::Write};
+use embedded_hal::{blocking::delay::DelayUs, blocking::serial::Write, digital::v2::OutputPin, serial::Read};
 
-use crate::protocol::{Command, ConnectionType, FirmwareInfo, IpAddresses, Response, WifiConnectionFailure, WiFiMode, ResolverAddresses};
+use crate::protocol::{Command, CommandError, MAX_LINKS, MAX_SEGMENT_LEN, UartConfig, BufferStatus, ConnectionOptions, ConnectionStatus, ConnectionType, FirmwareInfo, IpAddresses, DateTime, RawResponse, Response, Trace, WifiConnectionFailure, WiFiMode, ResolverAddresses, Summary};
 
-use heapless::{consts::{U16, U2}, spsc::{Consumer, Queue}, String};
-
-use log::info;
+use heapless::{consts::{U16, U2, U3, U32, U64}, spsc::{Consumer, Queue}, String, Vec};
 
 use crate::adapter::AdapterError::UnableToInitialize;
-use crate::ingress::Ingress;
+use crate::buffer::LineGate;
+use crate::ingress::{Ingress, IngressBuffer};
 use crate::network::Esp8266IpNetworkDriver;
 use core::fmt::Debug;
-use nom::lib::std::fmt::Formatter;
+use core::fmt::Formatter;
 use crate::protocol::Response::IpAddress;
 use drogue_network::dns::DnsError;
-use drogue_network::addr::{Ipv4Addr, HostAddr, HostSocketAddr};
+use drogue_network::addr::{Ipv4Addr, HostAddr, HostSocketAddr, SocketAddr};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AdapterError {
     UnableToInitialize,
     NoAvailableSockets,
@@ -25,31 +25,97 @@ pub enum AdapterError {
     WriteError,
     ReadError,
     InvalidSocket,
+    UnableToConfigure,
+    InvalidCommand(CommandError),
+    /// The board reset itself, dropping all connections.
+    ModuleReset,
+    /// The board's SNTP client did not synchronize its time yet.
+    TimeNotSynchronized,
+    /// The host could not be resolved or reached.
+    HostUnreachable,
 }
 
+/// State of a link, as tracked by the adapter.
+///
+/// | State                           | Event                         | Next state   | Commands                     |
+/// |---------------------------------|-------------------------------|--------------|------------------------------|
+/// | `Closed`                        | `open`                        | `Open`       |                              |
+/// | `Open`                          | connect succeeds              | `Connected`  | `AT+CIPSTART`                |
+/// | `Open`                          | connect fails, or board resets| `Closed`     | `AT+CIPSTART`                |
+/// | `Open`                          | connect is `ALREADY CONNECTED`| retried once | `AT+CIPCLOSE`, `AT+CIPSTART` |
+/// | `Connected`                     | `n,CLOSED`                    | `HalfClosed` |                              |
+/// | `Connected`                     | `close`                       | `Closed`     | `AT+CIPCLOSE`                |
+/// | `Open`, `HalfClosed`, `Reset`   | `close`                       | `Closed`     |                              |
+/// | all but `Closed`                | the board resets              | `Reset`      |                              |
+///
+/// Unread data stays readable in `HalfClosed`, after which reads fail. Data
+/// pending on a link is discarded when it is closed locally, even if the
+/// board reports an error. A `n,CLOSED` in any state but `Connected` is left
+/// over from a connection which was already released, and is ignored.
 #[derive(Debug)]
 enum SocketState {
+    /// Closed by the remote, possibly with unread data left.
     HalfClosed,
+    /// Free to be opened.
     Closed,
+    /// Allocated by the application, but not connected yet.
     Open,
     Connected,
+    /// Dropped by a reset of the board, until closed by the application.
+    Reset,
 }
 
 type Initialized<'a, Tx, Rx> = (Adapter<'a, Tx>, Ingress<'a, Rx>);
 
+/// Duration of the pulse on the reset pin.
+const RESET_PULSE_US: u32 = 10_000;
+/// Time for the board to boot and report `ready`.
+const READY_TIMEOUT_US: u32 = 5_000_000;
+/// Time for the board to respond to a command during initialization.
+const COMMAND_TIMEOUT_US: u32 = 1_000_000;
+/// Interval for polling the receiver, shorter than the time of one octet at 115,200 bps.
+const POLL_US: u32 = 20;
+/// Time of one octet at 115,200 bps, accounted for every octet or error received.
+const OCTET_US: u32 = 87;
+/// Number of attempts to bring up the board.
+const INIT_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for every further one.
+const INIT_BACKOFF_US: u32 = 100_000;
+/// Default time for the board to respond to a command, long enough for joining an access-point.
+const RESPONSE_TIMEOUT_US: u32 = 20_000_000;
+/// Baud rate the board starts with, unless configured otherwise.
+const DEFAULT_BAUD_RATE: u32 = 115_200;
+/// Time for both UARTs to settle after switching to a new configuration.
+const UART_SETTLE_US: u32 = 20_000;
+/// Time for the board to respond to a test command after switching the UART configuration.
+const UART_TEST_TIMEOUT_US: u32 = 100_000;
+/// Number of test commands sent after switching the UART configuration, as the first may be garbled.
+const UART_TEST_ATTEMPTS: u32 = 3;
+
 /// Initialize an ESP8266 board for usage as a Wifi-offload device.
 ///
+/// The board is reset through the `reset_pin`, falling back to `AT+RST` if it
+/// does not report being ready, and verified to respond to commands before being
+/// configured. Boot messages are discarded. Failed attempts are retried with
+/// increasing delays.
+///
 /// * tx: Serial transmitter.
 /// * rx: Serial receiver.
 /// * enable_pin: Pin connected to the ESP's `en` pin.
 /// * reset_pin: Pin connect to the ESP's `rst` pin.
+/// * delay: Delay provider, used for the reset pulse, timeouts and retries, and
+///   kept by the adapter for the timeouts of commands.
+/// * ingress_buffer: Buffer for octets received from the board.
 /// * response_queue: Queue for inbound AT command responses.
 /// * notification_queue: Queue for inbound unsolicited AT notification messages.
-pub fn initialize<'a, Tx, Rx, EnablePin, ResetPin>(
+#[allow(clippy::too_many_arguments)]
+pub fn initialize<'a, Tx, Rx, EnablePin, ResetPin, Delay>(
     mut tx: Tx,
     mut rx: Rx,
     enable_pin: &mut EnablePin,
     reset_pin: &mut ResetPin,
+    delay: &'a mut Delay,
+    ingress_buffer: &'a mut IngressBuffer,
     response_queue: &'a mut Queue<Response, U2>,
     notification_queue: &'a mut Queue<Response, U16>,
 ) -> Result<Initialized<'a, Tx, Rx>, AdapterError>
@@ -58,50 +124,32 @@ pub fn initialize<'a, Tx, Rx, EnablePin, ResetPin>(
         Rx: Read<u8>,
         EnablePin: OutputPin,
         ResetPin: OutputPin,
+        Delay: DelayUs<u32>,
 {
-    let mut buffer: [u8; 1024] = [0; 1024];
-    let mut pos = 0;
-
-    const READY: [u8; 7] = *b"ready\r\n";
-
-    let mut counter = 0;
-
     enable_pin
         .set_high()
         .map_err(|_| AdapterError::UnableToInitialize)?;
-    reset_pin
-        .set_high()
-        .map_err(|_| AdapterError::UnableToInitialize)?;
 
-    log::debug!("waiting for adapter to become ready");
-
-    loop {
-        let result = rx.read();
-        match result {
-            Ok(c) => {
-                buffer[pos] = c;
-                pos += 1;
-                if pos >= READY.len() && buffer[pos - READY.len()..pos] == READY {
-                    log::debug!("adapter is ready");
-                    disable_echo(&mut tx, &mut rx)?;
-                    enable_mux(&mut tx, &mut rx)?;
-                    set_recv_mode(&mut tx, &mut rx)?;
-                    return Ok(build_adapter_and_ingress(
-                        tx,
-                        rx,
-                        response_queue,
-                        notification_queue,
-                    ));
-                }
-            }
-            Err(nb::Error::WouldBlock) => {
-                continue;
-            }
-            Err(_) if counter > 10_000 => {
-                break;
+    let mut backoff = INIT_BACKOFF_US;
+    for attempt in 1..=INIT_ATTEMPTS {
+        debug!("initializing adapter, attempt {}", attempt);
+        match bring_up(&mut tx, &mut rx, reset_pin, delay) {
+            Ok(()) => {
+                return Ok(build_adapter_and_ingress(
+                    tx,
+                    rx,
+                    delay,
+                    ingress_buffer,
+                    response_queue,
+                    notification_queue,
+                ));
             }
-            Err(_) => {
-                counter += 1;
+            Err(e) => {
+                warn!("failed to initialize adapter: {:?}", e);
+                if attempt < INIT_ATTEMPTS {
+                    delay.delay_us(backoff);
+                    backoff *= 2;
+                }
             }
         }
     }
@@ -109,9 +157,47 @@ pub fn initialize<'a, Tx, Rx, EnablePin, ResetPin>(
     Err(AdapterError::UnableToInitialize)
 }
 
+fn bring_up<Tx, Rx, ResetPin, Delay>(
+    tx: &mut Tx,
+    rx: &mut Rx,
+    reset_pin: &mut ResetPin,
+    delay: &mut Delay,
+) -> Result<(), AdapterError>
+    where
+        Tx: Write<u8>,
+        Rx: Read<u8>,
+        ResetPin: OutputPin,
+        Delay: DelayUs<u32>,
+{
+    reset_pin
+        .set_low()
+        .map_err(|_| AdapterError::UnableToInitialize)?;
+    delay.delay_us(RESET_PULSE_US);
+    reset_pin
+        .set_high()
+        .map_err(|_| AdapterError::UnableToInitialize)?;
+
+    debug!("waiting for adapter to become ready");
+    if wait_for(rx, delay, b"ready\r\n", READY_TIMEOUT_US).is_err() {
+        debug!("no ready after reset pulse, trying AT+RST");
+        write_command(tx, b"AT+RST\r\n").map_err(|_| UnableToInitialize)?;
+        wait_for(rx, delay, b"ready\r\n", READY_TIMEOUT_US)?;
+    }
+    debug!("adapter is ready");
+
+    write_command(tx, b"AT\r\n").map_err(|_| UnableToInitialize)?;
+    wait_for_ok(rx, delay)?;
+
+    disable_echo(tx, rx, delay)?;
+    enable_mux(tx, rx, delay)?;
+    set_recv_mode(tx, rx, delay)
+}
+
 fn build_adapter_and_ingress<'a, Tx, Rx>(
     tx: Tx,
     rx: Rx,
+    delay: &'a mut dyn DelayUs<u32>,
+    ingress_buffer: &'a mut IngressBuffer,
     response_queue: &'a mut Queue<Response, U2>,
     notification_queue: &'a mut Queue<Response, U16>,
 ) -> Initialized<'a, Tx, Rx>
@@ -121,18 +207,34 @@ fn build_adapter_and_ingress<'a, Tx, Rx>(
 {
     let (response_producer, response_consumer) = response_queue.split();
     let (notification_producer, notification_consumer) = notification_queue.split();
+    let ingress = Ingress::new(rx, ingress_buffer, response_producer, notification_producer);
     (
         Adapter {
             tx,
+            delay,
+            timeout_us: RESPONSE_TIMEOUT_US,
+            lines: ingress.line_gate(),
             response_consumer,
             notification_consumer,
             sockets: initialize_sockets(),
+            mode: None,
+            resolvers: None,
+            credentials: None,
+            sntp: None,
+            data_info: false,
+            server_timeout: None,
+            rejoin: false,
+            uart: None,
+            uart_default: UartConfig::new(DEFAULT_BAUD_RATE),
+            uart_recovery: None,
+            statistics: AdapterStatistics::default(),
+            trace: Trace::default(),
         },
-        Ingress::new(rx, response_producer, notification_producer),
+        ingress,
     )
 }
 
-fn initialize_sockets() -> [Socket; 5] {
+fn initialize_sockets() -> [Socket; MAX_LINKS] {
     [
         Socket::new(),
         Socket::new(),
@@ -146,56 +248,119 @@ fn write_command<Tx>(tx: &mut Tx, cmd: &[u8]) -> Result<(), Tx::Error>
     where
         Tx: Write<u8>,
 {
-    for b in cmd.iter() {
-        nb::block!(tx.write(*b))?;
-    }
-    Ok(())
+    tx.bwrite_all(cmd)?;
+    tx.bflush()
 }
 
-fn disable_echo<Tx, Rx>(tx: &mut Tx, rx: &mut Rx) -> Result<(), AdapterError>
+fn disable_echo<Tx, Rx, Delay>(tx: &mut Tx, rx: &mut Rx, delay: &mut Delay) -> Result<(), AdapterError>
     where
         Tx: Write<u8>,
         Rx: Read<u8>,
+        Delay: DelayUs<u32>,
 {
     write_command(tx, b"ATE0\r\n").map_err(|_| UnableToInitialize)?;
-    Ok(wait_for_ok(rx).map_err(|_| UnableToInitialize)?)
+    wait_for_ok(rx, delay)
 }
 
-fn enable_mux<Tx, Rx>(tx: &mut Tx, rx: &mut Rx) -> Result<(), AdapterError>
+fn enable_mux<Tx, Rx, Delay>(tx: &mut Tx, rx: &mut Rx, delay: &mut Delay) -> Result<(), AdapterError>
     where
         Tx: Write<u8>,
         Rx: Read<u8>,
+        Delay: DelayUs<u32>,
 {
     write_command(tx, b"AT+CIPMUX=1\r\n").map_err(|_| UnableToInitialize)?;
-    Ok(wait_for_ok(rx).map_err(|_| UnableToInitialize)?)
+    wait_for_ok(rx, delay)
 }
 
-fn set_recv_mode<Tx, Rx>(tx: &mut Tx, rx: &mut Rx) -> Result<(), AdapterError>
+fn set_recv_mode<Tx, Rx, Delay>(tx: &mut Tx, rx: &mut Rx, delay: &mut Delay) -> Result<(), AdapterError>
     where
         Tx: Write<u8>,
         Rx: Read<u8>,
+        Delay: DelayUs<u32>,
 {
     write_command(tx, b"AT+CIPRECVMODE=1\r\n").map_err(|_| UnableToInitialize)?;
-    Ok(wait_for_ok(rx).map_err(|_| UnableToInitialize)?)
+    wait_for_ok(rx, delay)
 }
 
-fn wait_for_ok<Rx>(rx: &mut Rx) -> Result<(), Rx::Error>
+fn wait_for_ok<Rx, Delay>(rx: &mut Rx, delay: &mut Delay) -> Result<(), AdapterError>
     where
         Rx: Read<u8>,
+        Delay: DelayUs<u32>,
 {
-    let mut buf: [u8; 64] = [0; 64];
-    let mut pos = 0;
+    wait_for(rx, delay, b"OK\r\n", COMMAND_TIMEOUT_US)
+}
+
+/// Read from the board until `expected` was received, discarding everything before it.
+///
+/// Fails on `ERROR`, or once `timeout_us` passed, counting the time spent
+/// receiving octets as well as idle time, so a board which keeps sending
+/// garbage, or framing errors, times out too.
+fn wait_for<Rx, Delay>(
+    rx: &mut Rx,
+    delay: &mut Delay,
+    expected: &[u8],
+    timeout_us: u32,
+) -> Result<(), AdapterError>
+    where
+        Rx: Read<u8>,
+        Delay: DelayUs<u32>,
+{
+    // the most recently received octets, long enough for all expected values
+    let mut window = [0u8; 8];
+    let mut elapsed_us = 0;
 
     loop {
-        let b = nb::block!(rx.read())?;
-        buf[pos] = b;
-        pos += 1;
-        if buf[0..pos].ends_with(b"OK\r\n") {
-            return Ok(());
+        if elapsed_us >= timeout_us {
+            return Err(AdapterError::Timeout);
+        }
+        match rx.read() {
+            Ok(b) => {
+                elapsed_us += OCTET_US;
+                window.copy_within(1.., 0);
+                window[window.len() - 1] = b;
+                if window.ends_with(expected) {
+                    return Ok(());
+                }
+                if window.ends_with(b"ERROR\r\n") {
+                    return Err(AdapterError::UnableToInitialize);
+                }
+            }
+            Err(nb::Error::WouldBlock) => {
+                delay.delay_us(POLL_US);
+                elapsed_us += POLL_US;
+            }
+            Err(nb::Error::Other(_)) => {
+                // framing errors are expected while the board boots
+                elapsed_us += OCTET_US;
+            }
         }
     }
 }
 
+/// Traffic counters of a single link.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStatistics {
+    /// Octets acknowledged by the board as sent.
+    pub bytes_sent: usize,
+    /// Octets read from the board.
+    pub bytes_received: usize,
+}
+
+/// Snapshot of the counters maintained by the `Adapter`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdapterStatistics {
+    /// Commands written to the board.
+    pub commands: usize,
+    /// Commands answered with `ERROR`.
+    pub errors: usize,
+    /// Sends answered with `SEND FAIL`.
+    pub send_failures: usize,
+    /// Traffic per link, indexed by link ID.
+    pub links: [LinkStatistics; MAX_LINKS],
+}
+
 struct Socket {
     state: SocketState,
     available: usize,
@@ -230,14 +395,68 @@ impl Socket {
     }
 }
 
+/// Streams formatted commands directly to the serial transmitter.
+struct CommandWriter<'t, Tx>
+    where
+        Tx: Write<u8>,
+{
+    tx: &'t mut Tx,
+}
+
+impl<'t, Tx> core::fmt::Write for CommandWriter<'t, Tx>
+    where
+        Tx: Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.tx.bwrite_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
 pub struct Adapter<'a, Tx>
     where
         Tx: Write<u8>,
 {
     tx: Tx,
+    delay: &'a mut dyn DelayUs<u32>,
+    /// Time for the board to respond to a command.
+    timeout_us: u32,
+    lines: LineGate<'a>,
     response_consumer: Consumer<'a, Response, U2>,
     notification_consumer: Consumer<'a, Response, U16>,
-    sockets: [Socket; 5],
+    sockets: [Socket; MAX_LINKS],
+    /// Configuration to be restored after a reset of the board.
+    mode: Option<WiFiMode>,
+    resolvers: Option<ResolverAddresses>,
+    credentials: Option<Credentials>,
+    sntp: Option<SntpConfig>,
+    data_info: bool,
+    server_timeout: Option<u16>,
+    rejoin: bool,
+    /// UART configuration in effect, unless it is the one the board starts with.
+    uart: Option<UartConfig>,
+    /// UART configuration the board starts with.
+    uart_default: UartConfig,
+    /// Applies a UART configuration to the MCU's UART.
+    uart_recovery: Option<&'a mut UartRecovery>,
+    statistics: AdapterStatistics,
+    trace: Trace,
+}
+
+/// Applies a UART configuration to the MCU's UART, see `Adapter::set_uart_recovery`.
+pub type UartRecovery = dyn FnMut(&UartConfig) -> Result<(), ()>;
+
+/// Configuration of the board's SNTP client.
+struct SntpConfig {
+    timezone: i8,
+    servers: Vec<String<U64>, U3>,
+}
+
+/// Credentials of the most recently joined access-point.
+#[derive(Clone)]
+struct Credentials {
+    ssid: String<U32>,
+    password: String<U64>,
+    bssid: Option<[u8; 6]>,
 }
 
 impl<'a, Tx> Debug for Adapter<'a, Tx>
@@ -255,26 +474,128 @@ impl<'a, Tx> Adapter<'a, Tx>
         Tx: Write<u8>,
 {
     fn send<'c>(&mut self, command: Command<'c>) -> Result<Response, AdapterError> {
-        let bytes = command.as_bytes();
+        self.send_command(command)?;
+        self.wait_for_response()
+    }
+
+    /// Send a command, waiting for its response no longer than `timeout_us`.
+    fn send_within<'c>(&mut self, command: Command<'c>, timeout_us: u32) -> Result<Response, AdapterError> {
+        self.send_command(command)?;
+        self.wait_for_response_within(timeout_us)
+    }
+
+    fn send_command<'c>(&mut self, command: Command<'c>) -> Result<(), AdapterError> {
+        command.validate().map_err(AdapterError::InvalidCommand)?;
 
-        info!(
-            "writing command {}",
-            core::str::from_utf8(bytes.as_bytes()).unwrap()
-        );
-        for b in bytes.as_bytes().iter() {
-            nb::block!(self.tx.write(*b)).map_err(|_| AdapterError::WriteError)?;
+        // a reply arriving after its command timed out must not answer this one
+        while let Some(response) = self.response_consumer.dequeue() {
+            debug!("dropping stale {:?}", Summary(&response));
+        }
+
+        if self.trace != Trace::Off {
+            trace!("<-- {:?}", command);
+        }
+        command
+            .write_to(&mut CommandWriter { tx: &mut self.tx })
+            .map_err(|_| AdapterError::WriteError)?;
+        self.tx.bwrite_all(b"\r\n").map_err(|_| AdapterError::WriteError)?;
+        self.tx.bflush().map_err(|_| AdapterError::WriteError)?;
+        self.statistics.commands = self.statistics.commands.wrapping_add(1);
+        Ok(())
+    }
+
+    fn send_expect_ok<'c>(&mut self, command: Command<'c>) -> Result<(), AdapterError> {
+        match self.send(command)? {
+            Response::Ok => Ok(()),
+            _ => Err(AdapterError::UnableToInitialize),
         }
-        nb::block!(self.tx.write(b'\r')).map_err(|_| AdapterError::WriteError)?;
-        nb::block!(self.tx.write(b'\n')).map_err(|_| AdapterError::WriteError)?;
-        self.wait_for_response()
     }
 
     fn wait_for_response(&mut self) -> Result<Response, AdapterError> {
+        self.wait_for_response_within(self.timeout_us)
+    }
+
+    fn wait_for_response_within(&mut self, timeout_us: u32) -> Result<Response, AdapterError> {
+        loop {
+            match self.wait_for_line_within(timeout_us)? {
+                Response::Line(..) => {
+                    // not part of any modelled response, e.g. boot messages
+                }
+                response => return Ok(response),
+            }
+        }
+    }
+
+    fn wait_for_line(&mut self) -> Result<Response, AdapterError> {
+        self.wait_for_line_within(self.timeout_us)
+    }
+
+    /// Wait for the next response, failing with `AdapterError::Timeout` once
+    /// the board did not respond within `timeout_us`.
+    fn wait_for_line_within(&mut self, timeout_us: u32) -> Result<Response, AdapterError> {
+        let mut elapsed_us = 0;
         loop {
-            // busy loop until a response is received.
             if let Some(response) = self.response_consumer.dequeue() {
+                match response {
+                    Response::Error => {
+                        self.statistics.errors = self.statistics.errors.wrapping_add(1);
+                    }
+                    Response::SendFail => {
+                        self.statistics.send_failures = self.statistics.send_failures.wrapping_add(1);
+                    }
+                    _ => {}
+                }
                 return Ok(response);
             }
+            if elapsed_us >= timeout_us {
+                warn!("no response from the adapter within {} us", timeout_us);
+                return Err(AdapterError::Timeout);
+            }
+            self.delay.delay_us(POLL_US);
+            elapsed_us += POLL_US;
+        }
+    }
+
+    /// Set the time to wait for each response of the board, before failing with
+    /// `AdapterError::Timeout`. Defaults to 20 s, long enough for joining an
+    /// access-point.
+    ///
+    /// A reply arriving after its command timed out is discarded when sending the
+    /// next command.
+    pub fn set_timeout(&mut self, timeout_us: u32) {
+        self.timeout_us = timeout_us;
+    }
+
+    /// Send a command not modelled by this crate, e.g. `AT+CWHOSTNAME?`, without
+    /// the line ending.
+    ///
+    /// The lines received are collected until any other response terminates the
+    /// command, usually `OK` or `ERROR`. Lines beyond the capacity of the
+    /// `RawResponse` are dropped. Fails with `AdapterError::Timeout` if the
+    /// command is not answered, like any other command.
+    pub fn send_raw(&mut self, command: &str) -> Result<RawResponse, AdapterError> {
+        self.lines.set_open(true);
+        let result = self.collect_raw(command);
+        self.lines.set_open(false);
+        result
+    }
+
+    fn collect_raw(&mut self, command: &str) -> Result<RawResponse, AdapterError> {
+        self.send_command(Command::Raw(command))?;
+
+        let mut response = RawResponse::new();
+        loop {
+            match self.wait_for_line()? {
+                Response::Line(line, len) => {
+                    if !response.push_line(line, len) {
+                        warn!("dropping line of raw response");
+                    }
+                }
+                r => {
+                    response.finish(r);
+                    return Ok(response);
+                }
+            }
         }
     }
 
@@ -307,11 +628,63 @@ impl<'a, Tx> Adapter<'a, Tx>
         let command = Command::SetMode(mode);
 
         match self.send(command) {
-            Ok(Response::Ok) => Ok(()),
+            Ok(Response::Ok) => {
+                self.mode.replace(mode);
+                Ok(())
+            }
             _ => Err(()),
         }
     }
 
+    /// Change the configuration of the board's UART, e.g. to a higher baud rate.
+    ///
+    /// The board acknowledges the command using its previous configuration, after
+    /// which `reconfigure` is called to apply the new configuration to the MCU's
+    /// UART. After letting both settle, the link is verified with a test command,
+    /// which is retried if it is not answered, as the first one may be garbled.
+    ///
+    /// A configuration which is not persisted is lost when the board resets itself,
+    /// and only restored by `recover` if the MCU's UART may be reconfigured as well,
+    /// see `set_uart_recovery`.
+    ///
+    /// * `config`: The new UART configuration.
+    /// * `persist`: Store the configuration in the board's flash, to be used after a reset.
+    /// * `reconfigure`: Applies the configuration to the MCU's UART.
+    pub fn set_uart<F, E>(
+        &mut self,
+        config: UartConfig,
+        persist: bool,
+        reconfigure: F,
+    ) -> Result<(), AdapterError>
+        where
+            F: FnOnce(&UartConfig) -> Result<(), E>,
+    {
+        let command = Command::SetUart { config, persist };
+        match self.send(command)? {
+            Response::Ok => {}
+            _ => return Err(AdapterError::UnableToConfigure),
+        }
+        if persist {
+            self.uart_default = config;
+            self.uart = None;
+        } else {
+            self.uart.replace(config);
+        }
+
+        reconfigure(&config).map_err(|_| AdapterError::UnableToConfigure)?;
+        self.delay.delay_us(UART_SETTLE_US);
+
+        // the first command after switching may be garbled, and not be answered at all
+        for attempt in 1..=UART_TEST_ATTEMPTS {
+            match self.send_within(Command::Test, UART_TEST_TIMEOUT_US) {
+                Ok(Response::Ok) => return Ok(()),
+                Ok(_) => debug!("unexpected response to test command, attempt {}", attempt),
+                Err(e) => debug!("failed to test UART configuration, attempt {}: {:?}", attempt, e),
+            }
+        }
+        Err(AdapterError::UnableToConfigure)
+    }
+
     /// Join a wifi access-point.
     ///
     /// The board will expect to obtain an IP address from DHCP.
@@ -323,15 +696,55 @@ impl<'a, Tx> Adapter<'a, Tx>
         ssid: &'c str,
         password: &'c str,
     ) -> Result<(), WifiConnectionFailure> {
-        let command = Command::JoinAp { ssid, password };
+        self.join_ap(ssid, password, None)
+    }
+
+    /// Join a specific wifi access-point, identified by its BSSID.
+    ///
+    /// Useful when several access-points share the same SSID.
+    ///
+    /// * `ssid`: The access-point's SSID to join
+    /// * `password`: The password for the access-point.
+    /// * `bssid`: The MAC address of the access-point.
+    pub fn join_bssid<'c>(
+        &mut self,
+        ssid: &'c str,
+        password: &'c str,
+        bssid: [u8; 6],
+    ) -> Result<(), WifiConnectionFailure> {
+        self.join_ap(ssid, password, Some(bssid))
+    }
+
+    fn join_ap<'c>(
+        &mut self,
+        ssid: &'c str,
+        password: &'c str,
+        bssid: Option<[u8; 6]>,
+    ) -> Result<(), WifiConnectionFailure> {
+        let command = Command::JoinAp { ssid, password, bssid };
 
         match self.send(command) {
-            Ok(Response::Ok) => {
+            Ok(Response::Ok) if self.rejoin => {
+                let mut credentials = Credentials {
+                    ssid: String::new(),
+                    password: String::new(),
+                    bssid,
+                };
+                // lengths were validated when sending the command
+                if credentials.ssid.push_str(ssid).is_ok()
+                    && credentials.password.push_str(password).is_ok()
+                {
+                    self.credentials.replace(credentials);
+                }
                 Ok(())
             }
+            Ok(Response::Ok) => Ok(()),
             Ok(Response::WifiConnectionFailure(reason)) => {
                 Err(reason)
             }
+            Err(AdapterError::InvalidCommand(CommandError::InvalidArgument(e))) => {
+                Err(WifiConnectionFailure::InvalidArgument(e))
+            }
             _ => {
                 Err(WifiConnectionFailure::ConnectionFailed)
             }
@@ -348,20 +761,279 @@ impl<'a, Tx> Adapter<'a, Tx>
     }
 
     pub fn set_dns_resolvers(&mut self, resolver1: Ipv4Addr, resolver2: Option<Ipv4Addr>) -> Result<(), ()> {
-        let command = Command::SetDnsResolvers(
-            ResolverAddresses {
-                resolver1,
-                resolver2
-            }
-        );
+        let resolvers = ResolverAddresses {
+            resolver1,
+            resolver2
+        };
+        let command = Command::SetDnsResolvers(resolvers);
 
         if let Ok(Response::Ok) = self.send(command) {
+            self.resolvers.replace(resolvers);
             Ok(())
         } else {
             Err(())
         }
     }
 
+    /// Configure the board's SNTP client, with the timezone in hours from UTC and
+    /// up to `MAX_SNTP_SERVERS` servers. Without servers, the board's defaults are used.
+    pub fn set_sntp_config(
+        &mut self,
+        enable: bool,
+        timezone: i8,
+        servers: &[&str],
+    ) -> Result<(), AdapterError> {
+        let command = Command::SetSntpConfig {
+            enable,
+            timezone,
+            servers,
+        };
+
+        match self.send(command)? {
+            Response::Ok => {
+                self.sntp = if enable {
+                    let mut config = SntpConfig {
+                        timezone,
+                        servers: Vec::new(),
+                    };
+                    // servers were validated when sending the command
+                    for server in servers {
+                        let _ = config.servers.push(String::from(*server));
+                    }
+                    Some(config)
+                } else {
+                    None
+                };
+                Ok(())
+            }
+            _ => Err(AdapterError::UnableToConfigure),
+        }
+    }
+
+    /// Get the current time from the board's SNTP client, in UTC.
+    pub fn get_time(&mut self) -> Result<DateTime, AdapterError> {
+        match self.send(Command::QuerySntpTime)? {
+            Response::SntpTime(local) => {
+                // the board reports 1970 until it got a response from a server
+                if local.year < 2000 {
+                    return Err(AdapterError::TimeNotSynchronized);
+                }
+                let offset = self.sntp.as_ref().map(|sntp| sntp.timezone).unwrap_or_default();
+                Ok(DateTime::from_unix_timestamp(
+                    local.unix_timestamp() - offset as i64 * 3_600,
+                ))
+            }
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    /// Report the sender along with received data, e.g. for UDP sockets.
+    ///
+    /// Disabled by default.
+    pub fn set_data_info(&mut self, enable: bool) -> Result<(), AdapterError> {
+        match self.send(Command::SetDataInfo(enable))? {
+            Response::Ok => {
+                self.data_info = enable;
+                Ok(())
+            }
+            _ => Err(AdapterError::UnableToConfigure),
+        }
+    }
+
+    /// Set the idle timeout, in seconds, after which the board closes links
+    /// accepted by its server, up to `MAX_SERVER_TIMEOUT`. `0` disables it.
+    pub fn set_server_timeout(&mut self, timeout: u16) -> Result<(), AdapterError> {
+        match self.send(Command::SetServerTimeout(timeout))? {
+            Response::Ok => {
+                self.server_timeout.replace(timeout);
+                Ok(())
+            }
+            _ => Err(AdapterError::UnableToConfigure),
+        }
+    }
+
+    /// Get the idle timeout, in seconds, of links accepted by the board's server.
+    pub fn get_server_timeout(&mut self) -> Result<u16, AdapterError> {
+        match self.send(Command::QueryServerTimeout)? {
+            Response::ServerTimeout(timeout) => Ok(timeout),
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    /// Query the board's view of its connections.
+    pub fn query_connection_status(&mut self) -> Result<ConnectionStatus, AdapterError> {
+        match self.send(Command::QueryConnectionStatus)? {
+            Response::ConnectionStatus(status) => Ok(status),
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    /// Reconcile the socket table with the board's view of its connections, in
+    /// case a notification was missed.
+    ///
+    /// Connected sockets unknown to the board are half-closed, open sockets known
+    /// to it are connected. Links of closed sockets are closed on the board.
+    pub fn reconcile_sockets(&mut self) -> Result<ConnectionStatus, AdapterError> {
+        self.process_notifications();
+        let status = self.query_connection_status()?;
+
+        for link_id in 0..MAX_LINKS {
+            let open = status.link(link_id).is_some();
+            match self.sockets[link_id].state {
+                SocketState::Connected if !open => {
+                    warn!("link {} was closed by the adapter", link_id);
+                    self.sockets[link_id].state = SocketState::HalfClosed;
+                }
+                SocketState::Open if open => {
+                    self.sockets[link_id].state = SocketState::Connected;
+                }
+                SocketState::Closed if open => {
+                    warn!("closing stray link {}", link_id);
+                    self.close(link_id)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(status)
+    }
+
+    /// Query the octets pending on the board for all links, indexed by link ID.
+    ///
+    /// The sockets are resynchronized with the result, in case a notification
+    /// of received data was missed.
+    pub fn query_receive_lengths(&mut self) -> Result<[usize; MAX_LINKS], AdapterError> {
+        self.process_notifications();
+        match self.send(Command::QueryReceiveLengths)? {
+            Response::ReceiveLengths(lengths) => {
+                for (socket, len) in self.sockets.iter_mut().zip(lengths.iter()) {
+                    if socket.available != *len {
+                        debug!("resynchronizing available octets: {} -> {}", socket.available, len);
+                        socket.available = *len;
+                    }
+                }
+                Ok(lengths)
+            }
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    /// Ping a host, given by IP address or hostname, returning the round-trip
+    /// time in milliseconds.
+    ///
+    /// Fails with `AdapterError::Timeout` if the board gave up waiting for a reply,
+    /// or did not answer within the adapter's timeout, see `set_timeout`.
+    pub fn ping(&mut self, host: &str) -> Result<usize, AdapterError> {
+        match self.send(Command::Ping { host })? {
+            Response::PingReply(time) => Ok(time),
+            Response::PingTimeout => Err(AdapterError::Timeout),
+            _ => Err(AdapterError::HostUnreachable),
+        }
+    }
+
+    /// Rejoin the most recently joined access-point after the board reset itself.
+    ///
+    /// Disabled by default. The credentials, including the password, are only kept
+    /// while enabled, so it must be enabled before joining. Disabling it discards them.
+    pub fn set_rejoin_on_reset(&mut self, rejoin: bool) {
+        self.rejoin = rejoin;
+        if !rejoin {
+            self.credentials = None;
+        }
+    }
+
+    /// Lend the adapter a way to reconfigure the MCU's UART, to restore a UART
+    /// configuration which was set without persisting it after the board reset itself.
+    ///
+    /// The board restarts with its default configuration, to which the MCU's UART is
+    /// switched back before the configuration is applied again using `set_uart`.
+    pub fn set_uart_recovery(
+        &mut self,
+        reconfigure: &'a mut UartRecovery,
+    ) {
+        self.uart_recovery.replace(reconfigure);
+    }
+
+    /// Restore the configuration of the board after it reset itself.
+    ///
+    /// This happens automatically when the board reports being ready again, once the
+    /// notification is processed by the next socket operation. A command pending while
+    /// the board resets is not answered, and fails with `AdapterError::Timeout`. All
+    /// connections are dropped, the sockets fail with `AdapterError::ModuleReset`
+    /// until closed.
+    ///
+    /// While a UART configuration set without persisting it is in effect, the board's
+    /// `ready` cannot be understood, and the reset only shows as commands timing out,
+    /// after which the application should recover explicitly. The configuration is
+    /// restored using the callback given to `set_uart_recovery`, failing with
+    /// `AdapterError::UnableToConfigure` without one.
+    pub fn recover(&mut self) -> Result<(), AdapterError> {
+        for socket in self.sockets.iter_mut() {
+            if !socket.is_closed() {
+                socket.state = SocketState::Reset;
+            }
+            socket.available = 0;
+        }
+
+        if let Some(config) = self.uart {
+            let reconfigure = self.uart_recovery.take().ok_or(AdapterError::UnableToConfigure)?;
+            let result = reconfigure(&self.uart_default)
+                .map_err(|_| AdapterError::UnableToConfigure)
+                .and_then(|_| {
+                    self.delay.delay_us(UART_SETTLE_US);
+                    self.set_uart(config, false, |config| reconfigure(config))
+                });
+            self.uart_recovery.replace(reconfigure);
+            result?;
+        }
+
+        self.send_expect_ok(Command::DisableEcho)?;
+        self.send_expect_ok(Command::EnableMux)?;
+        self.send_expect_ok(Command::SetPassiveRecvMode)?;
+        if self.data_info {
+            self.set_data_info(true)?;
+        }
+        if let Some(timeout) = self.server_timeout {
+            self.set_server_timeout(timeout)?;
+        }
+        if let Some(mode) = self.mode {
+            self.set_mode(mode).map_err(|_| AdapterError::UnableToInitialize)?;
+        }
+        if let Some(resolvers) = self.resolvers {
+            self.set_dns_resolvers(resolvers.resolver1, resolvers.resolver2)
+                .map_err(|_| AdapterError::UnableToInitialize)?;
+        }
+        if let Some(sntp) = self.sntp.take() {
+            let result = {
+                let mut servers: Vec<&str, U3> = Vec::new();
+                for server in sntp.servers.iter() {
+                    // both have the capacity of MAX_SNTP_SERVERS
+                    let _ = servers.push(server.as_str());
+                }
+                self.set_sntp_config(true, sntp.timezone, &servers)
+            };
+            self.sntp.replace(sntp);
+            result?;
+        }
+        if self.rejoin {
+            if let Some(credentials) = self.credentials.clone() {
+                self.join_ap(&credentials.ssid, &credentials.password, credentials.bssid)
+                    .map_err(|_| AdapterError::UnableToInitialize)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the verbosity of the trace of commands sent to the board. Secrets
+    /// are never traced.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = trace;
+    }
+
+    /// Snapshot of the counters, e.g. to be published as telemetry.
+    pub fn statistics(&self) -> AdapterStatistics {
+        self.statistics
+    }
+
     /// Consume the adapter and produce a `NetworkStack`.
     pub fn into_network_stack(self) -> Esp8266IpNetworkDriver<'a, Tx> {
         Esp8266IpNetworkDriver::new(self)
@@ -375,22 +1047,32 @@ impl<'a, Tx> Adapter<'a, Tx>
         while let Some(response) = self.notification_consumer.dequeue() {
             match response {
                 Response::DataAvailable { link_id, len } => {
-                    self.sockets[link_id].available += len;
+                    if let Some(socket) = self.sockets.get_mut(link_id) {
+                        socket.available = socket.available.saturating_add(len);
+                    }
                 }
                 Response::Connect(_) => {}
                 Response::Closed(link_id) => {
-                    match self.sockets[link_id].state {
-                        SocketState::HalfClosed => {
-                            self.sockets[link_id].state = SocketState::Closed;
-                        }
-                        SocketState::Open | SocketState::Connected => {
-                            self.sockets[link_id].state = SocketState::HalfClosed;
-                        }
-                        SocketState::Closed => {
-                            // nothing
+                    if let Some(socket) = self.sockets.get_mut(link_id) {
+                        match socket.state {
+                            SocketState::Connected => {
+                                socket.state = SocketState::HalfClosed;
+                            }
+                            SocketState::Open
+                            | SocketState::HalfClosed
+                            | SocketState::Closed
+                            | SocketState::Reset => {
+                                // stale, the link was already released
+                            }
                         }
                     }
                 }
+                Response::Ready => {
+                    warn!("adapter reset itself, recovering");
+                    if let Err(e) = self.recover() {
+                        error!("failed to recover adapter: {:?}", e);
+                    }
+                }
                 _ => { /* ignore */ }
             }
         }
@@ -403,6 +1085,7 @@ impl<'a, Tx> Adapter<'a, Tx>
             .enumerate()
             .find(|(_, e)| e.is_closed())
         {
+            *socket = Socket::new();
             socket.state = SocketState::Open;
             return Ok(index);
         }
@@ -411,13 +1094,23 @@ impl<'a, Tx> Adapter<'a, Tx>
     }
 
     pub(crate) fn close(&mut self, link_id: usize) -> Result<(), AdapterError> {
-        let command = Command::CloseConnection(link_id);
-        match self.send(command) {
-            Ok(Response::Ok) | Ok(Response::UnlinkFail) => {
-                self.sockets[link_id].state = SocketState::Closed;
-                Ok(())
-            },
-            _=> Err(AdapterError::UnableToClose),
+        match self.sockets[link_id].state {
+            // a closed link may still be connected on the module, see `reconcile_sockets`
+            SocketState::Connected | SocketState::Closed => {}
+            SocketState::Open | SocketState::HalfClosed | SocketState::Reset => {
+                // the module holds no connection for the link
+                self.sockets[link_id] = Socket::new();
+                return Ok(());
+            }
+        }
+
+        let result = self.send(Command::CloseConnection(link_id));
+        // consume the `CLOSED` notification before the link can be reused
+        self.process_notifications();
+        self.sockets[link_id] = Socket::new();
+        match result {
+            Ok(Response::Ok) | Ok(Response::UnlinkFail) => Ok(()),
+            _ => Err(AdapterError::UnableToClose),
         }
     }
 
@@ -425,14 +1118,45 @@ impl<'a, Tx> Adapter<'a, Tx>
         &mut self,
         link_id: usize,
         remote: HostSocketAddr,
+        keep_alive: Option<u16>,
     ) -> Result<(), AdapterError> {
-        let command = Command::StartConnection(link_id, ConnectionType::TCP, remote.as_socket_addr());
-        if let Ok(Response::Connect(..)) = self.send(command) {
-            self.sockets[link_id].state = SocketState::Connected;
-            return Ok(());
+        match self.sockets[link_id].state {
+            SocketState::Open => {}
+            SocketState::Reset => return Err(AdapterError::ModuleReset),
+            _ => return Err(AdapterError::InvalidSocket),
+        }
+
+        let options = ConnectionOptions { keep_alive };
+        let command = Command::StartConnection(link_id, ConnectionType::TCP, remote.as_socket_addr(), options);
+        let mut result = self.send(command);
+        if let Ok(Response::AlreadyConnected) = result {
+            warn!("link {} was left connected, closing it", link_id);
+            let _ = self.send(Command::CloseConnection(link_id));
+            let command = Command::StartConnection(link_id, ConnectionType::TCP, remote.as_socket_addr(), options);
+            result = self.send(command);
+        }
+        // consume any `CLOSED` notification of a previous connection first
+        self.process_notifications();
+        if let SocketState::Reset = self.sockets[link_id].state {
+            // the board reset itself while connecting, dropping the connection
+            self.sockets[link_id] = Socket::new();
+            return Err(AdapterError::ModuleReset);
         }
 
-        Err(AdapterError::UnableToOpen)
+        match result {
+            Ok(Response::Connect(..)) => {
+                self.sockets[link_id].state = SocketState::Connected;
+                Ok(())
+            }
+            result => {
+                // release the link, the socket is consumed by the failed connect
+                self.sockets[link_id] = Socket::new();
+                match result {
+                    Err(e @ AdapterError::InvalidCommand(_)) => Err(e),
+                    _ => Err(AdapterError::UnableToOpen),
+                }
+            }
+        }
     }
 
     pub(crate) fn write(
@@ -441,49 +1165,198 @@ impl<'a, Tx> Adapter<'a, Tx>
         buffer: &[u8],
     ) -> nb::Result<usize, AdapterError> {
         self.process_notifications();
+        self.check_connected(link_id)?;
 
         let command = Command::Send {
             link_id,
             len: buffer.len(),
         };
 
-        if let Ok(response) = self.send(command) {
-            if let Response::Ok = response {
-                if let Ok(response) = self.wait_for_response() {
-                    if let Response::ReadyForData = response {
-                        for b in buffer.iter() {
-                            nb::block!(self.tx.write(*b))
-                                .map_err(|_| nb::Error::from(AdapterError::WriteError))?;
-                        }
-                        let mut data_sent: Option<usize> = None;
-                        loop {
-                            match self.wait_for_response() {
-                                Ok(Response::ReceivedDataToSend(len)) => {
-                                    data_sent.replace(len);
-                                }
-                                Ok(Response::SendOk) => {
-                                    return Ok(data_sent.unwrap_or_default());
-                                }
-                                _ => {
-                                    break; // unknown response
-                                }
-                            }
-                        }
-                    }
-                }
+        if let Ok(Response::Ok) = self.send(command) {
+            if let Ok(Response::ReadyForData) = self.wait_for_response() {
+                self.tx
+                    .bwrite_all(buffer)
+                    .and_then(|_| self.tx.bflush())
+                    .map_err(|_| nb::Error::from(AdapterError::WriteError))?;
+                return Ok(self.wait_for_sent(link_id)?);
             }
         }
         Err(nb::Error::from(AdapterError::WriteError))
     }
 
+    /// Wait for the board to send the data written, returning its length.
+    fn wait_for_sent(&mut self, link_id: usize) -> Result<usize, AdapterError> {
+        let mut data_sent: Option<usize> = None;
+        loop {
+            match self.wait_for_response() {
+                Ok(Response::ReceivedDataToSend(len)) => {
+                    data_sent.replace(len);
+                }
+                Ok(Response::SendOk) => {
+                    let len = data_sent.unwrap_or_default();
+                    let link = &mut self.statistics.links[link_id];
+                    link.bytes_sent = link.bytes_sent.wrapping_add(len);
+                    return Ok(len);
+                }
+                _ => {
+                    return Err(AdapterError::WriteError); // unknown response
+                }
+            }
+        }
+    }
+
+    /// Start sending data of unknown length, up to `MAX_SEGMENT_LEN` octets,
+    /// which is written using `write_data` and terminated by `finish_terminated`.
+    pub(crate) fn start_terminated(&mut self, link_id: usize) -> Result<(), AdapterError> {
+        self.process_notifications();
+        self.check_connected(link_id)?;
+
+        let command = Command::SendTerminated {
+            link_id,
+            len: MAX_SEGMENT_LEN,
+        };
+        if let Response::Ok = self.send(command)? {
+            if let Response::ReadyForData = self.wait_for_response()? {
+                return Ok(());
+            }
+        }
+        Err(AdapterError::WriteError)
+    }
+
+    /// Write data started by `start_terminated`, which must already be escaped.
+    pub(crate) fn write_data(&mut self, data: &[u8]) -> Result<(), AdapterError> {
+        self.tx.bwrite_all(data).map_err(|_| AdapterError::WriteError)
+    }
+
+    /// Terminate data started by `start_terminated`, returning its length.
+    pub(crate) fn finish_terminated(&mut self, link_id: usize) -> Result<usize, AdapterError> {
+        self.tx
+            .bwrite_all(b"\\0")
+            .and_then(|_| self.tx.bflush())
+            .map_err(|_| AdapterError::WriteError)?;
+        self.wait_for_sent(link_id)
+    }
+
+    /// Check that a link may be used for receiving or querying.
+    fn check_open(&self, link_id: usize) -> Result<(), AdapterError> {
+        match self.sockets[link_id].state {
+            SocketState::Reset => Err(AdapterError::ModuleReset),
+            SocketState::Closed => Err(AdapterError::InvalidSocket),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a link may be used for sending.
+    fn check_connected(&self, link_id: usize) -> Result<(), AdapterError> {
+        match self.sockets[link_id].state {
+            SocketState::Connected => Ok(()),
+            SocketState::Reset => Err(AdapterError::ModuleReset),
+            _ => Err(AdapterError::InvalidSocket),
+        }
+    }
+
+    /// Queue a segment into the send buffer of a link, returning its segment ID
+    /// without waiting for it to be sent.
+    ///
+    /// The board refuses segments while its buffer is full, which is reported
+    /// as `WouldBlock`. Any other refusal fails, e.g. for a link the board
+    /// already closed.
+    pub(crate) fn write_buffered(
+        &mut self,
+        link_id: usize,
+        buffer: &[u8],
+    ) -> nb::Result<usize, AdapterError> {
+        self.process_notifications();
+        self.check_connected(link_id)?;
+
+        let command = Command::SendBuffered {
+            link_id,
+            len: buffer.len(),
+        };
+        let segment_id = match self.send(command)? {
+            Response::SegmentQueued { segment_id, .. } => segment_id,
+            Response::Error => return Err(self.refused_segment(link_id, buffer.len())),
+            _ => return Err(nb::Error::Other(AdapterError::WriteError)),
+        };
+        if let Response::ReadyForData = self.wait_for_response()? {
+            self.tx
+                .bwrite_all(buffer)
+                .and_then(|_| self.tx.bflush())
+                .map_err(|_| nb::Error::from(AdapterError::WriteError))?;
+            if let Response::ReceivedDataToSend(len) = self.wait_for_response()? {
+                let link = &mut self.statistics.links[link_id];
+                link.bytes_sent = link.bytes_sent.wrapping_add(len);
+                return Ok(segment_id);
+            }
+        }
+        Err(nb::Error::Other(AdapterError::WriteError))
+    }
+
+    /// Tell a full send buffer from other reasons for refusing a segment.
+    fn refused_segment(&mut self, link_id: usize, len: usize) -> nb::Error<AdapterError> {
+        // the link may have been closed by the remote already
+        self.process_notifications();
+        if let Err(e) = self.check_connected(link_id) {
+            return nb::Error::Other(e);
+        }
+        match self.buffer_status(link_id) {
+            Ok(status) if status.free < len => nb::Error::WouldBlock,
+            _ => nb::Error::Other(AdapterError::WriteError),
+        }
+    }
+
+    pub(crate) fn buffer_status(&mut self, link_id: usize) -> Result<BufferStatus, AdapterError> {
+        self.check_open(link_id)?;
+        match self.send(Command::QueryBufferStatus(link_id))? {
+            Response::BufferStatus(status) => Ok(status),
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    pub(crate) fn is_segment_sent(
+        &mut self,
+        link_id: usize,
+        segment_id: usize,
+    ) -> Result<bool, AdapterError> {
+        self.check_open(link_id)?;
+        match self.send(Command::CheckSegment { link_id, segment_id })? {
+            Response::SegmentStatus { sent, .. } => Ok(sent),
+            _ => Err(AdapterError::ReadError),
+        }
+    }
+
+    /// Reset the segment IDs of a link, which the board refuses while segments
+    /// are queued.
+    pub(crate) fn reset_buffer(&mut self, link_id: usize) -> Result<(), AdapterError> {
+        self.check_open(link_id)?;
+        match self.send(Command::ResetBuffer(link_id))? {
+            Response::Ok => Ok(()),
+            _ => Err(AdapterError::WriteError),
+        }
+    }
+
     pub(crate) fn read(
         &mut self,
         link_id: usize,
         buffer: &mut [u8],
     ) -> nb::Result<usize, AdapterError> {
+        self.read_from(link_id, buffer).map(|(len, _)| len)
+    }
+
+    /// Read from a link, along with the sender if reported by the board, see
+    /// `set_data_info`.
+    pub(crate) fn read_from(
+        &mut self,
+        link_id: usize,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, Option<SocketAddr>), AdapterError> {
         self.process_notifications();
 
-        if matches!( self.sockets[link_id].state, SocketState::Closed ) {
+        if matches!(self.sockets[link_id].state, SocketState::Reset) {
+            return Err(nb::Error::Other(AdapterError::ModuleReset));
+        }
+
+        if matches!( self.sockets[link_id].state, SocketState::Closed | SocketState::Open ) {
             return Err(nb::Error::Other(AdapterError::InvalidSocket));
         }
 
@@ -506,24 +1379,37 @@ impl<'a, Tx> Adapter<'a, Tx>
         };
 
         match self.send(command) {
-            Ok(Response::DataReceived(inbound, len)) => {
-                for (i, b) in inbound[0..len].iter().enumerate() {
-                    buffer[i] = *b;
+            Ok(Response::DataReceived(inbound, len, remote)) => {
+                if len > actual_len {
+                    return Err(nb::Error::Other(AdapterError::ReadError));
                 }
-                self.sockets[link_id].available -= len;
-                Ok(len)
+                buffer[0..len].copy_from_slice(&inbound[0..len]);
+                let socket = &mut self.sockets[link_id];
+                socket.available = socket.available.saturating_sub(len);
+                let link = &mut self.statistics.links[link_id];
+                link.bytes_received = link.bytes_received.wrapping_add(len);
+                Ok((len, remote))
+            }
+            Ok(Response::Ok) => {
+                // nothing pending on the board, the counter drifted
+                self.sockets[link_id].available = 0;
+                Err(nb::Error::WouldBlock)
             }
-            Ok(Response::Ok) => Err(nb::Error::WouldBlock),
             _=> Err(nb::Error::Other(AdapterError::ReadError)),
         }
     }
 
+    pub(crate) fn available(&mut self, link_id: usize) -> Result<usize, AdapterError> {
+        self.check_open(link_id)?;
+        Ok(self.query_receive_lengths()?[link_id])
+    }
+
     pub(crate) fn is_connected(&self, link_id: usize) -> Result<bool, AdapterError> {
         Ok(match self.sockets[link_id].state {
             SocketState::HalfClosed => {
                 self.sockets[link_id].available > 0
             }
-            SocketState::Closed => {
+            SocketState::Closed | SocketState::Reset => {
                 false
             }
             SocketState::Open => {
@@ -1,107 +1,392 @@
 use crate::parser;
 use crate::protocol::Response;
-use moveslice::Moveslice;
+use core::cell::UnsafeCell;
+use core::cmp::min;
 use core::str::from_utf8;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-pub(crate) struct Buffer {
-    buffer: [u8; 4096],
-    pos: usize,
-    needs_parse: bool,
+/// Capacity of the ingress ring buffer, must be a power of two.
+pub(crate) const RING_LEN: usize = 4096;
+
+/// Capacity of the scratch area used to parse responses which wrap around the
+/// end of the ring buffer.
+const SCRATCH_LEN: usize = crate::BUFFER_LEN + 256;
+
+/// Storage for octets received from the board.
+///
+/// The buffer is shared lock-free between a single writer, filled from the
+/// USART ISR, and a single reader, parsing responses in the digest loop.
+pub struct IngressBuffer {
+    buffer: UnsafeCell<[u8; RING_LEN]>,
+    /// Position of the next octet to be read, only advanced by the reader.
+    head: AtomicUsize,
+    /// Position of the next octet to be written, only advanced by the writer.
+    tail: AtomicUsize,
+    /// Number of octets dropped because the buffer was full, only advanced by the writer.
+    dropped: AtomicUsize,
+    /// Highest number of octets held at once, only advanced by the writer.
+    high_water: AtomicUsize,
+    /// Whether the board was asked to pause sending, set by the writer and
+    /// cleared by the reader.
+    paused: AtomicBool,
+    /// Whether unmodelled lines are collected as responses, see `LineGate`.
+    collect_lines: AtomicBool,
 }
 
-impl Buffer {
-    pub fn new() -> Self {
-        Buffer {
-            buffer: [0; 4096],
-            pos: 0,
-            needs_parse: false,
+// The writer and reader only ever access disjoint regions of the buffer,
+// which are handed over through the `head` and `tail` positions.
+unsafe impl Sync for IngressBuffer {}
+
+impl IngressBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new([0; RING_LEN]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            collect_lines: AtomicBool::new(false),
         }
     }
 
+    pub(crate) fn split(&mut self) -> (BufferWriter<'_>, BufferReader<'_>) {
+        *self.head.get_mut() = 0;
+        *self.tail.get_mut() = 0;
+        *self.dropped.get_mut() = 0;
+        *self.high_water.get_mut() = 0;
+        *self.paused.get_mut() = false;
+        *self.collect_lines.get_mut() = false;
+        let ring: &IngressBuffer = self;
+        (
+            BufferWriter { ring },
+            BufferReader {
+                ring,
+                scratch: [0; SCRATCH_LEN],
+                needs_parse: false,
+                parsed_tail: 0,
+                parse_failures: 0,
+            },
+        )
+    }
+}
+
+impl Default for IngressBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writing half of the `IngressBuffer`.
+pub struct BufferWriter<'a> {
+    ring: &'a IngressBuffer,
+}
+
+impl<'a> BufferWriter<'a> {
+    /// Number of octets waiting to be parsed.
+    pub fn len(&self) -> usize {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Whether the board was asked to pause sending.
+    pub(crate) fn paused(&self) -> bool {
+        self.ring.paused.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn pause(&self) {
+        self.ring.paused.store(true, Ordering::Release);
+    }
+
     pub fn write(&mut self, octet: u8) -> Result<(), u8> {
-        if self.pos >= self.buffer.len() {
-            Err(octet)
-        } else {
-            self.buffer[self.pos] = octet;
-            self.pos += 1;
-            self.needs_parse = true;
-            Ok(())
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= RING_LEN {
+            self.drop_octets(1);
+            return Err(octet);
         }
+        unsafe {
+            (self.ring.buffer.get() as *mut u8)
+                .add(tail % RING_LEN)
+                .write(octet);
+        }
+        self.ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        self.update_high_water(tail.wrapping_sub(head) + 1);
+        Ok(())
+    }
+
+    /// Write as many octets as fit into the buffer, returning their number.
+    pub fn write_slice(&mut self, data: &[u8]) -> usize {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+        let len = min(RING_LEN - tail.wrapping_sub(head), data.len());
+
+        let start = tail % RING_LEN;
+        let first = min(len, RING_LEN - start);
+        let ring = self.ring.buffer.get() as *mut u8;
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), ring.add(start), first);
+            core::ptr::copy_nonoverlapping(data.as_ptr().add(first), ring, len - first);
+        }
+        self.ring.tail.store(tail.wrapping_add(len), Ordering::Release);
+        self.update_high_water(tail.wrapping_sub(head) + len);
+        if len < data.len() {
+            self.drop_octets(data.len() - len);
+        }
+        len
+    }
+
+    fn update_high_water(&mut self, len: usize) {
+        if len > self.ring.high_water.load(Ordering::Relaxed) {
+            self.ring.high_water.store(len, Ordering::Relaxed);
+        }
+    }
+
+    fn drop_octets(&mut self, count: usize) {
+        let dropped = self.ring.dropped.load(Ordering::Relaxed);
+        self.ring.dropped.store(dropped.wrapping_add(count), Ordering::Relaxed);
+    }
+}
+
+/// Lets the `Adapter` tell the digest loop whether lines which are not part of
+/// any modelled response are expected, while a raw command is outstanding.
+/// Otherwise such lines, e.g. boot messages, are dropped instead of filling up
+/// the response queue.
+#[derive(Copy, Clone)]
+pub(crate) struct LineGate<'a> {
+    ring: &'a IngressBuffer,
+}
+
+impl<'a> LineGate<'a> {
+    pub(crate) fn is_open(&self) -> bool {
+        self.ring.collect_lines.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_open(&self, open: bool) {
+        self.ring.collect_lines.store(open, Ordering::Release);
+    }
+}
+
+/// Reading half of the `IngressBuffer`.
+pub struct BufferReader<'a> {
+    ring: &'a IngressBuffer,
+    scratch: [u8; SCRATCH_LEN],
+    /// Set once a response was parsed, as the remainder may hold another one.
+    needs_parse: bool,
+    /// Write position at the last parse attempt.
+    parsed_tail: usize,
+    /// Number of lines discarded because they could not be parsed.
+    parse_failures: usize,
+}
+
+impl<'a> BufferReader<'a> {
+    /// Number of octets waiting to be parsed.
+    pub fn len(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Total number of octets dropped because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the board was asked to pause sending.
+    pub(crate) fn paused(&self) -> bool {
+        self.ring.paused.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn resume(&self) {
+        self.ring.paused.store(false, Ordering::Release);
+    }
+
+    pub(crate) fn line_gate(&self) -> LineGate<'a> {
+        LineGate { ring: self.ring }
+    }
+
+    /// Highest number of octets held at once.
+    pub fn high_water(&self) -> usize {
+        self.ring.high_water.load(Ordering::Relaxed)
+    }
+
+    /// Number of lines discarded because they could not be parsed.
+    pub fn parse_failures(&self) -> usize {
+        self.parse_failures
     }
 
     pub fn parse(&mut self) -> Result<Response, ()> {
-        if self.pos == 0 {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        let len = tail.wrapping_sub(head);
+
+        if len == 0 {
             return Ok(Response::None);
         }
-        if !self.needs_parse {
+        if !self.needs_parse && tail == self.parsed_tail {
             return Ok(Response::None);
         }
         self.needs_parse = false;
+        self.parsed_tail = tail;
+
+        let start = head % RING_LEN;
+        let data: &[u8] = if start + len <= RING_LEN {
+            unsafe {
+                core::slice::from_raw_parts((self.ring.buffer.get() as *const u8).add(start), len)
+            }
+        } else {
+            // linearize the wrapped octets, only as many as a response may span.
+            let len = min(len, SCRATCH_LEN);
+            let first = min(len, RING_LEN - start);
+            let ring = self.ring.buffer.get() as *const u8;
+            unsafe {
+                core::ptr::copy_nonoverlapping(ring.add(start), self.scratch.as_mut_ptr(), first);
+                core::ptr::copy_nonoverlapping(ring, self.scratch.as_mut_ptr().add(first), len - first);
+            }
+            &self.scratch[0..len]
+        };
 
-        let str = from_utf8(&self.buffer[0..self.pos]);
-        match str {
+        match from_utf8(data) {
             Ok(s) => {
-                log::debug!("parsing {} [{}]", self.pos, s);
+                trace!("parsing {} [{}]", data.len(), s);
             },
             Err(e) => {
-                let s = from_utf8(&self.buffer[0..e.valid_up_to()]).unwrap();
-                log::debug!("parsing {} [{}<truncated>] ({})", self.pos, s, e);
+                let s = from_utf8(&data[0..e.valid_up_to()]).unwrap();
+                trace!("parsing {} [{}<truncated>]", data.len(), s);
             },
         }
 
         let mut ret = Ok(Response::None);
+        let mut consumed = 0;
 
-        if let Ok((remainder, response)) = parser::parse(&self.buffer[0..self.pos]) {
-            let len = remainder.len();
-            if len > 0 {
-                let start = self.pos - len;
-                (&mut self.buffer[..]).moveslice(start..start + len, 0);
-                self.pos = len;
+        match parser::parse(data) {
+            Ok((remainder, response)) => {
+                consumed = data.len() - remainder.len();
                 self.needs_parse = true;
-            } else {
-                self.pos = 0;
+                ret = Ok(response);
+            }
+            Err(nom::Err::Incomplete(_)) => {
+                // wait for more octets
+            }
+            Err(_) => {
+                // skip the unparsable line, so the following ones are not blocked,
+                // or everything without a line ending, which would block forever.
+                consumed = match data.iter().position(|b| *b == b'\n') {
+                    Some(end) => end + 1,
+                    None => data.len(),
+                };
+                warn!("discarding {} unparsable octets", consumed);
+                self.needs_parse = true;
+                self.parse_failures = self.parse_failures.wrapping_add(1);
             }
-            ret = Ok(response);
-        }
-
-        /*
-        let mut dump_len = self.pos;
-        if dump_len > 10 {
-            dump_len = 10;
         }
 
-        log::info!("-------->>");
-        log::info!("remainder: {} bytes", self.pos);
-        for (i, b) in self.buffer[0..dump_len].iter().enumerate() {
-            log::info!( "{}: {} {:#x?}", i, *b as char, *b );
-        }
+        self.ring.head.store(head.wrapping_add(consumed), Ordering::Release);
+        ret
+    }
+}
 
-        let remainder = from_utf8(&self.buffer[0..self.pos]);
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        match remainder {
-            Ok(s) => {
-                log::info!("{}", s);
-            },
-            Err(e) => {
-                let s = from_utf8( &self.buffer[0..e.valid_up_to()]).unwrap();
-                log::info!("{}<truncated>", s)
-            },
+    #[test]
+    fn test_full() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, reader) = ring.split();
+        for _ in 0..RING_LEN {
+            assert_eq!(writer.write(b'x'), Ok(()));
         }
+        assert_eq!(writer.write(b'y'), Err(b'y'));
+        assert_eq!(writer.write_slice(b"zz"), 0);
+        assert_eq!(reader.len(), RING_LEN);
+        assert_eq!(reader.dropped(), 3);
+        assert_eq!(reader.high_water(), RING_LEN);
+    }
 
-        let mut dump_len = self.pos;
-        if dump_len > 30 {
-            dump_len = 30;
+    #[test]
+    fn test_write_slice() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, mut reader) = ring.split();
+        let frame = b"\r\n+IPD,1,5\r\n";
+        for _ in 0..1000 {
+            assert_eq!(writer.write_slice(frame), frame.len());
+            assert!(matches!(
+                reader.parse(),
+                Ok(Response::DataAvailable { link_id: 1, len: 5 })
+            ));
         }
+        assert_eq!(writer.write_slice(&[b'x'; RING_LEN + 1]), RING_LEN);
+        assert_eq!(writer.write_slice(b"x"), 0);
+    }
 
-        log::info!("-------->>");
-        for (i, b ) in self.buffer[self.pos - dump_len..self.pos].iter().enumerate() {
-            log::info!( "{}: {} {:#x?}", i + (self.pos - dump_len), *b as char, *b );
+    #[test]
+    fn test_wrap_around() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, mut reader) = ring.split();
+        let frame = b"\r\n+IPD,1,5\r\n";
+        for i in 0..1000 {
+            // split each frame, so some of them wrap around the end.
+            let (first, second) = frame.split_at(i % frame.len());
+            for octet in first.iter() {
+                writer.write(*octet).unwrap();
+            }
+            assert!(matches!(reader.parse(), Ok(Response::None)));
+            for octet in second.iter() {
+                writer.write(*octet).unwrap();
+            }
+            assert!(matches!(
+                reader.parse(),
+                Ok(Response::DataAvailable { link_id: 1, len: 5 })
+            ));
         }
-        log::info!("<<--------");
+        assert!(matches!(reader.parse(), Ok(Response::None)));
+    }
 
+    #[test]
+    fn test_wrap_around_beyond_scratch() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, mut reader) = ring.split();
+        let frame = b"\r\n+IPD,1,5\r\n";
+        for _ in 0..250 {
+            writer.write_slice(frame);
+            assert!(matches!(reader.parse(), Ok(Response::DataAvailable { .. })));
+        }
+        // more than `SCRATCH_LEN` octets before the end of the ring
+        for _ in 0..100 {
+            assert_eq!(writer.write_slice(frame), frame.len());
+        }
+        for _ in 0..100 {
+            assert!(matches!(
+                reader.parse(),
+                Ok(Response::DataAvailable { link_id: 1, len: 5 })
+            ));
+        }
+        assert!(matches!(reader.parse(), Ok(Response::None)));
+        assert_eq!(reader.len(), 0);
+    }
 
-         */
-        //Ok(Response::None)
-        ret
+    #[test]
+    fn test_parse_failures() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, mut reader) = ring.split();
+        writer.write_slice(b"+IPD,9,5\r\n\r\nOK\r\n");
+        assert!(matches!(reader.parse(), Ok(Response::None)));
+        assert!(matches!(reader.parse(), Ok(Response::Ok)));
+        assert_eq!(reader.parse_failures(), 1);
+        assert_eq!(reader.high_water(), 16);
+    }
+    #[test]
+    fn test_garbage_without_line_ending() {
+        let mut ring = IngressBuffer::new();
+        let (mut writer, mut reader) = ring.split();
+        // too long for a line, so no line ending is coming to skip to
+        writer.write_slice(&[b'x'; SCRATCH_LEN]);
+        assert!(matches!(reader.parse(), Ok(Response::None)));
+        assert_eq!(reader.len(), 0);
+        assert_eq!(reader.parse_failures(), 1);
+        writer.write_slice(b"\r\nOK\r\n");
+        assert!(matches!(reader.parse(), Ok(Response::Ok)));
     }
 }
@@ -0,0 +1,64 @@
+use crate::protocol::Response;
+use drogue_network::addr::Ipv4Addr;
+
+/// Unsolicited events of the board, to be consumed by the application.
+///
+/// Events are emitted by the `IngressDigest` as soon as they are received,
+/// see `Ingress::with_events`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Joined an access-point.
+    WifiConnected,
+    /// Left an access-point, or failed to join it.
+    WifiDisconnected,
+    /// Got an IP address from the access-point.
+    GotIp,
+    /// A connection was established.
+    SocketConnected(usize),
+    /// A connection was closed, by either side.
+    SocketClosed(usize),
+    /// Data was received on a connection, and may be read.
+    DataAvailable { link_id: usize, len: usize },
+    /// A station joined the soft-AP.
+    StationConnected([u8; 6]),
+    /// A station left the soft-AP.
+    StationDisconnected([u8; 6]),
+    /// A station of the soft-AP was assigned an IP address.
+    StationIp([u8; 6], #[cfg_attr(feature = "defmt", defmt(Display2Format))] Ipv4Addr),
+    /// A segment of the send buffer was sent, see `Esp8266IpNetworkDriver::write_buffered`.
+    SegmentSent { link_id: usize, segment_id: usize },
+    /// A segment of the send buffer failed to be sent.
+    SegmentFailed { link_id: usize, segment_id: usize },
+    /// The board reset itself, dropping all connections.
+    ModuleReset,
+}
+
+impl Event {
+    pub(crate) fn from_response(response: &Response) -> Option<Self> {
+        Some(match response {
+            Response::WifiConnected => Event::WifiConnected,
+            Response::WifiDisconnect => Event::WifiDisconnected,
+            Response::GotIp => Event::GotIp,
+            Response::Connect(link_id) => Event::SocketConnected(*link_id),
+            Response::Closed(link_id) => Event::SocketClosed(*link_id),
+            Response::DataAvailable { link_id, len } => Event::DataAvailable {
+                link_id: *link_id,
+                len: *len,
+            },
+            Response::StationConnected(mac) => Event::StationConnected(*mac),
+            Response::StationDisconnected(mac) => Event::StationDisconnected(*mac),
+            Response::StationIp(mac, ip) => Event::StationIp(*mac, *ip),
+            Response::SegmentSent { link_id, segment_id } => Event::SegmentSent {
+                link_id: *link_id,
+                segment_id: *segment_id,
+            },
+            Response::SegmentFailed { link_id, segment_id } => Event::SegmentFailed {
+                link_id: *link_id,
+                segment_id: *segment_id,
+            },
+            Response::Ready => Event::ModuleReset,
+            _ => return None,
+        })
+    }
+}
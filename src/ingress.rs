@@ -1,20 +1,122 @@
-use crate::{buffer::Buffer, protocol::Response};
+use crate::buffer::{BufferReader, BufferWriter, LineGate, RING_LEN};
+use crate::event::Event;
+use crate::protocol::{Response, Summary, Trace};
 use heapless::{
     consts::{U16, U2},
     spsc::Producer,
 };
 
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::serial::Read;
 use nb::Error;
 
-pub struct Ingress<'a, Rx>
+pub use crate::buffer::IngressBuffer;
+
+/// Fill level of the `IngressBuffer` at which the board is asked to pause sending.
+const RTS_PAUSE: usize = RING_LEN / 4 * 3;
+/// Fill level of the `IngressBuffer` at which the board may resume sending.
+const RTS_RESUME: usize = RING_LEN / 4;
+
+/// Snapshot of the counters maintained by the `Ingress`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IngressStatistics {
+    /// Octets dropped because the `IngressBuffer` was full.
+    pub dropped_octets: usize,
+    /// Highest number of octets held in the `IngressBuffer` at once.
+    pub buffer_high_water: usize,
+    /// Lines discarded because they could not be parsed.
+    pub parse_failures: usize,
+    /// Responses dropped because the response queue was full.
+    pub response_overflows: usize,
+    /// Notifications dropped because the notification queue was full.
+    pub notification_overflows: usize,
+    /// Events dropped because the event queue was full.
+    pub event_overflows: usize,
+}
+
+/// Placeholder for an `Ingress` without an RTS pin.
+pub struct NoRts;
+
+impl OutputPin for NoRts {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The MCU's RTS pin, shared by both halves of the `Ingress`.
+///
+/// Only one half drives the pin at a time, handed over through the paused flag
+/// of the `IngressBuffer`: the `IngressIsr` while the board may send, raising it
+/// as the buffer fills up, and the `IngressDigest` while the board is paused,
+/// lowering it once the buffer drained.
+struct SharedRts<'a, Rts> {
+    pin: Option<NonNull<Rts>>,
+    _pin: PhantomData<&'a mut Rts>,
+}
+
+// The pin is never accessed by both halves at once, see above.
+unsafe impl<'a, Rts> Send for SharedRts<'a, Rts> where Rts: Send {}
+
+impl<'a, Rts> SharedRts<'a, Rts>
+    where
+        Rts: OutputPin,
+{
+    fn none() -> Self {
+        Self {
+            pin: None,
+            _pin: PhantomData,
+        }
+    }
+
+    fn new(pin: &'a mut Rts) -> Self {
+        Self {
+            pin: Some(NonNull::from(pin)),
+            _pin: PhantomData,
+        }
+    }
+
+    fn share(&self) -> Self {
+        Self {
+            pin: self.pin,
+            _pin: PhantomData,
+        }
+    }
+
+    /// Drive the pin, returning whether it was set.
+    ///
+    /// Safety: the caller must own the pin, as handed over through the paused flag.
+    unsafe fn set(&mut self, high: bool) -> bool {
+        match self.pin {
+            Some(mut pin) if high => pin.as_mut().set_high().is_ok(),
+            Some(mut pin) => pin.as_mut().set_low().is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Receives octets from the board and turns them into responses and
+/// notifications for the `Adapter`.
+///
+/// The `Ingress` may be split into an `IngressIsr` and an `IngressDigest`,
+/// which share the `IngressBuffer` without locking, so the ISR may preempt
+/// the digest loop at any time.
+pub struct Ingress<'a, Rx, Rts = NoRts>
     where
         Rx: Read<u8>,
+        Rts: OutputPin,
 {
-    rx: Rx,
-    response_producer: Producer<'a, Response, U2>,
-    notification_producer: Producer<'a, Response, U16>,
-    buffer: Buffer,
+    isr: IngressIsr<'a, Rx, Rts>,
+    digest: IngressDigest<'a, Rts>,
 }
 
 impl<'a, Rx> Ingress<'a, Rx>
@@ -23,24 +125,158 @@ impl<'a, Rx> Ingress<'a, Rx>
 {
     pub fn new(
         rx: Rx,
+        buffer: &'a mut IngressBuffer,
         response_producer: Producer<'a, Response, U2>,
         notification_producer: Producer<'a, Response, U16>,
     ) -> Self {
+        let (writer, reader) = buffer.split();
         Self {
-            rx,
-            response_producer,
-            notification_producer,
-            buffer: Buffer::new(),
+            isr: IngressIsr {
+                rx,
+                buffer: writer,
+                rts: SharedRts::none(),
+                dma_position: 0,
+            },
+            digest: IngressDigest {
+                response_producer,
+                notification_producer,
+                event_producer: None,
+                buffer: reader,
+                rts: SharedRts::none(),
+                overflows: Overflows::default(),
+                trace: Trace::default(),
+            },
         }
     }
 
+    /// Drive the MCU's RTS pin, connected to the board's CTS pin, from the fill
+    /// level of the `IngressBuffer`.
+    ///
+    /// The pin is set high by the ISR to pause the board when the buffer is
+    /// filling up, even if the digest loop is not running, and set low by the
+    /// digest loop once it has caught up. Hardware flow control must be enabled
+    /// on the board too, see `Adapter::set_uart`.
+    pub fn with_rts<Rts>(self, rts: &'a mut Rts) -> Ingress<'a, Rx, Rts>
+        where
+            Rts: OutputPin,
+    {
+        let rts = SharedRts::new(rts);
+        let isr = IngressIsr {
+            rx: self.isr.rx,
+            buffer: self.isr.buffer,
+            rts: rts.share(),
+            dma_position: self.isr.dma_position,
+        };
+        let mut digest = IngressDigest {
+            response_producer: self.digest.response_producer,
+            notification_producer: self.digest.notification_producer,
+            event_producer: self.digest.event_producer,
+            buffer: self.digest.buffer,
+            rts,
+            overflows: self.digest.overflows,
+            trace: self.digest.trace,
+        };
+        // the digest loop owns the pin until it lowers it
+        isr.buffer.pause();
+        digest.resume();
+        Ingress {
+            isr,
+            digest,
+        }
+    }
+}
+
+impl<'a, Rx, Rts> Ingress<'a, Rx, Rts>
+    where
+        Rx: Read<u8>,
+        Rts: OutputPin,
+{
+    /// Emit unsolicited events of the board to the application.
+    ///
+    /// Events are enqueued from the digest loop. If the application does not
+    /// keep up, further events are dropped until there is room in the queue.
+    pub fn with_events(mut self, event_producer: Producer<'a, Event, U16>) -> Self {
+        self.digest.event_producer.replace(event_producer);
+        self
+    }
+
+    /// Set the verbosity of the trace of responses received from the board.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.digest.set_trace(trace)
+    }
+
     /// Method to be called from USART or appropriate ISR.
-    pub fn isr(&mut self) -> Result<(), u8> {
+    pub fn isr(&mut self) -> Result<(), usize> {
+        self.isr.isr()
+    }
+
+    /// Method to be called with a block of received octets, e.g. from a DMA transfer.
+    pub fn ingest(&mut self, data: &[u8]) -> Result<(), usize> {
+        self.isr.ingest(data)
+    }
+
+    /// Method to be called with a circular DMA buffer and its current write position.
+    pub fn ingest_circular(&mut self, dma_buffer: &[u8], position: usize) -> Result<(), usize> {
+        self.isr.ingest_circular(dma_buffer, position)
+    }
+
+    /// Digest and process the existing ingressed buffer to
+    /// emit appropriate responses and notifications back
+    pub fn digest(&mut self) {
+        self.digest.digest()
+    }
+
+    /// Total number of octets dropped because the `IngressBuffer` was full.
+    pub fn dropped(&self) -> usize {
+        self.digest.dropped()
+    }
+
+    /// Snapshot of the counters, e.g. to be published as telemetry.
+    pub fn statistics(&self) -> IngressStatistics {
+        self.digest.statistics()
+    }
+
+    pub(crate) fn line_gate(&self) -> LineGate<'a> {
+        self.digest.buffer.line_gate()
+    }
+
+    /// Split into the halves to be driven from the ISR and the digest loop.
+    pub fn split(self) -> (IngressIsr<'a, Rx, Rts>, IngressDigest<'a, Rts>) {
+        (self.isr, self.digest)
+    }
+}
+
+/// Half of the `Ingress` to be driven from the USART ISR.
+pub struct IngressIsr<'a, Rx, Rts = NoRts>
+    where
+        Rx: Read<u8>,
+        Rts: OutputPin,
+{
+    rx: Rx,
+    buffer: BufferWriter<'a>,
+    rts: SharedRts<'a, Rts>,
+    /// Position within the circular DMA buffer up to which octets were ingested.
+    dma_position: usize,
+}
+
+impl<'a, Rx, Rts> IngressIsr<'a, Rx, Rts>
+    where
+        Rx: Read<u8>,
+        Rts: OutputPin,
+{
+    /// Method to be called from USART or appropriate ISR.
+    ///
+    /// Reads all available octets. If the buffer is full, the number of dropped
+    /// octets is returned as error.
+    pub fn isr(&mut self) -> Result<(), usize> {
+        let mut dropped = 0;
         loop {
             let result = self.rx.read();
             match result {
                 Ok(d) => {
-                    self.write(d)?;
+                    if self.write(d).is_err() {
+                        dropped += 1;
+                    }
                 }
                 Err(e) => {
                     match e {
@@ -53,24 +289,161 @@ impl<'a, Rx> Ingress<'a, Rx>
                 }
             }
         }
-        Ok(())
+        if dropped > 0 {
+            Err(dropped)
+        } else {
+            Ok(())
+        }
     }
 
     fn write(&mut self, octet: u8) -> Result<(), u8> {
-        self.buffer.write(octet)?;
-        Ok(())
+        let result = self.buffer.write(octet);
+        self.pause();
+        result
+    }
+
+    /// Ask the board to pause sending once the buffer is filling up.
+    fn pause(&mut self) {
+        if !self.buffer.paused() && self.buffer.len() >= RTS_PAUSE {
+            // the ISR owns the pin until the paused flag is set
+            if unsafe { self.rts.set(true) } {
+                self.buffer.pause();
+            }
+        }
+    }
+
+    /// Method to be called with a block of received octets, e.g. from a DMA transfer.
+    ///
+    /// If the buffer is full, the number of dropped octets is returned as error.
+    pub fn ingest(&mut self, data: &[u8]) -> Result<(), usize> {
+        let written = self.buffer.write_slice(data);
+        self.pause();
+        if written < data.len() {
+            Err(data.len() - written)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Method to be called with a circular DMA buffer and its current write position,
+    /// from the UART idle-line and the DMA half- and full-transfer interrupts.
+    ///
+    /// Ingests all octets from the position of the previous call up to `position`,
+    /// wrapping around the end of the buffer. If the buffer is full, the number of
+    /// dropped octets is returned as error.
+    pub fn ingest_circular(&mut self, dma_buffer: &[u8], position: usize) -> Result<(), usize> {
+        if dma_buffer.is_empty() {
+            return Ok(());
+        }
+        let position = position % dma_buffer.len();
+        let last = core::mem::replace(&mut self.dma_position, position);
+
+        if position >= last {
+            self.ingest(&dma_buffer[last..position])
+        } else {
+            let dropped = self.ingest(&dma_buffer[last..]).err().unwrap_or(0)
+                + self.ingest(&dma_buffer[..position]).err().unwrap_or(0);
+            if dropped > 0 {
+                Err(dropped)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+}
+
+/// Half of the `Ingress` to be driven from the digest loop.
+pub struct IngressDigest<'a, Rts = NoRts>
+    where
+        Rts: OutputPin,
+{
+    response_producer: Producer<'a, Response, U2>,
+    notification_producer: Producer<'a, Response, U16>,
+    event_producer: Option<Producer<'a, Event, U16>>,
+    buffer: BufferReader<'a>,
+    rts: SharedRts<'a, Rts>,
+    overflows: Overflows,
+    trace: Trace,
+}
+
+/// Number of items dropped because their queue was full.
+#[derive(Copy, Clone, Default)]
+struct Overflows {
+    responses: usize,
+    notifications: usize,
+    events: usize,
+}
+
+impl<'a, Rts> IngressDigest<'a, Rts>
+    where
+        Rts: OutputPin,
+{
+    /// Total number of octets dropped because the `IngressBuffer` was full.
+    pub fn dropped(&self) -> usize {
+        self.buffer.dropped()
+    }
+
+    /// Snapshot of the counters, e.g. to be published as telemetry.
+    pub fn statistics(&self) -> IngressStatistics {
+        IngressStatistics {
+            dropped_octets: self.buffer.dropped(),
+            buffer_high_water: self.buffer.high_water(),
+            parse_failures: self.buffer.parse_failures(),
+            response_overflows: self.overflows.responses,
+            notification_overflows: self.overflows.notifications,
+            event_overflows: self.overflows.events,
+        }
+    }
+
+    /// Let the board resume sending once the buffer has drained.
+    fn resume(&mut self) {
+        if self.buffer.paused() && self.buffer.len() <= RTS_RESUME {
+            // the digest loop owns the pin until the paused flag is cleared
+            if unsafe { self.rts.set(false) } {
+                self.buffer.resume();
+            }
+        }
+    }
+
+    /// Set the verbosity of the trace of responses received from the board.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = trace;
+    }
+
+    fn emit(&mut self, response: &Response) {
+        if let (Some(producer), Some(event)) =
+            (self.event_producer.as_mut(), Event::from_response(response))
+        {
+            if let Err(event) = producer.enqueue(event) {
+                warn!("failed to enqueue event {:?}", event);
+                self.overflows.events = self.overflows.events.wrapping_add(1);
+            }
+        }
+    }
+
+    fn enqueue_response(&mut self, response: Response) {
+        if let Err(response) = self.response_producer.enqueue(response) {
+            error!("failed to enqueue response {:?}", response);
+            self.overflows.responses = self.overflows.responses.wrapping_add(1);
+        }
     }
 
     /// Digest and process the existing ingressed buffer to
     /// emit appropriate responses and notifications back
     pub fn digest(&mut self) {
         let result = self.buffer.parse();
-
+        self.resume();
 
         if let Ok(response) = result {
             if ! matches!(response, Response::None ) {
-                log::info!("--> {:?}", response);
+                match self.trace {
+                    Trace::Off => {}
+                    Trace::Summary => trace!("--> {:?}", Summary(&response)),
+                    Trace::Full => trace!("--> {:?}", response),
+                }
             }
+            self.emit(&response);
             match response {
                 Response::None => {}
                 Response::Ok
@@ -87,24 +460,49 @@ impl<'a, Rx> Ingress<'a, Rx>
                 | Response::Resolvers(..)
                 | Response::DnsFail
                 | Response::UnlinkFail
-                | Response::IpAddresses(..) => {
-                    if let Err(response) = self.response_producer.enqueue(response) {
-                        log::error!("failed to enqueue response {:?}", response);
+                | Response::AlreadyConnected
+                | Response::IpAddresses(..)
+                | Response::SntpTime(..)
+                | Response::PingReply(..)
+                | Response::PingTimeout
+                | Response::ConnectionStatus(..)
+                | Response::ReceiveLengths(..)
+                | Response::ServerTimeout(..)
+                | Response::SegmentQueued { .. }
+                | Response::BufferStatus(..)
+                | Response::SegmentStatus { .. } => {
+                    self.enqueue_response(response);
+                }
+                Response::Line(..) => {
+                    if self.buffer.line_gate().is_open() {
+                        self.enqueue_response(response);
+                    } else {
+                        debug!("dropping unsolicited {:?}", Summary(&response));
                     }
                 }
-                Response::Closed(..) | Response::DataAvailable { .. } => {
+                Response::Closed(..)
+                | Response::DataAvailable { .. }
+                | Response::SegmentSent { .. }
+                | Response::SegmentFailed { .. }
+                | Response::Ready => {
                     if let Err(response) = self.notification_producer.enqueue(response) {
-                        log::error!("failed to enqueue notification {:?}", response);
+                        error!("failed to enqueue notification {:?}", response);
+                        self.overflows.notifications = self.overflows.notifications.wrapping_add(1);
                     }
                 }
                 Response::WifiConnected => {
-                    log::info!("wifi connected");
+                    info!("wifi connected");
                 }
                 Response::WifiDisconnect => {
-                    log::info!("wifi disconnect");
+                    info!("wifi disconnect");
                 }
                 Response::GotIp => {
-                    log::info!("wifi got ip");
+                    info!("wifi got ip");
+                }
+                Response::StationConnected(..)
+                | Response::StationDisconnected(..)
+                | Response::StationIp(..) => {
+                    // only of interest to the application
                 }
             }
         }
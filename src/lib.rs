@@ -1,7 +1,12 @@
 #![no_std]
+#![allow(clippy::result_unit_err)]
+
+#[macro_use]
+mod fmt;
 
 pub mod adapter;
 mod buffer;
+pub mod event;
 pub mod ingress;
 pub mod network;
 mod num;
@@ -17,6 +22,18 @@ pub const BUFFER_LEN: usize = 1024;
 #[cfg(all(feature="2k",not(feature="1k")))]
 pub const BUFFER_LEN: usize = 2048;
 
+/// Internals exposed to the fuzz targets in `fuzz/`.
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub mod fuzzing {
+    pub use crate::buffer::{BufferReader, BufferWriter, IngressBuffer};
+    pub use crate::parser::parse;
+
+    pub fn split(buffer: &mut IngressBuffer) -> (BufferWriter<'_>, BufferReader<'_>) {
+        buffer.split()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
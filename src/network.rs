@@ -1,11 +1,13 @@
-use crate::adapter::{Adapter, AdapterError};
-use embedded_hal::serial::Write;
+use crate::adapter::{Adapter, AdapterError, AdapterStatistics};
+use crate::protocol::{BufferStatus, ConnectionStatus, MAX_SEGMENT_LEN};
+use embedded_hal::blocking::serial::Write;
 
 use core::cell::RefCell;
 use drogue_network::addr::{
     HostAddr,
     HostSocketAddr,
-    IpAddr
+    IpAddr,
+    SocketAddr,
 };
 use drogue_network::tcp::{
     Mode,
@@ -14,7 +16,7 @@ use drogue_network::tcp::{
     TcpImplError,
 };
 use core::fmt::Debug;
-use nom::lib::std::fmt::Formatter;
+use core::fmt::Formatter;
 use heapless::{
     String,
     consts::{
@@ -50,6 +52,82 @@ impl<'a, Tx> Esp8266IpNetworkDriver<'a, Tx>
             adapter: RefCell::new(adapter),
         }
     }
+
+    /// Snapshot of the adapter's counters, e.g. to be published as telemetry.
+    pub fn statistics(&self) -> AdapterStatistics {
+        self.adapter.borrow().statistics()
+    }
+
+    /// Octets pending on the board for a socket, queried from the board rather
+    /// than tracked through notifications.
+    pub fn available(&self, socket: &TcpSocket) -> Result<usize, TcpError> {
+        self.adapter.borrow_mut().available(socket.link_id).map_err(TcpError::from)
+    }
+
+    /// Read from a socket, along with the sender of the data if reported by the
+    /// board, see `Adapter::set_data_info`. Never blocks.
+    pub fn read_from(
+        &self,
+        socket: &mut TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, Option<SocketAddr>), TcpError> {
+        self.adapter
+            .borrow_mut()
+            .read_from(socket.link_id, buffer)
+            .map_err(|e| e.map(TcpError::from))
+    }
+
+    /// Queue a segment of up to `MAX_SEGMENT_LEN` octets into the board's send
+    /// buffer of a socket, returning its segment ID without waiting for it to be
+    /// sent. Segment IDs start at `1`.
+    ///
+    /// Whether a segment was sent is reported through the `SegmentSent` and
+    /// `SegmentFailed` events, or may be checked using `is_segment_sent`.
+    pub fn write_buffered(
+        &self,
+        socket: &mut TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, TcpError> {
+        self.adapter
+            .borrow_mut()
+            .write_buffered(socket.link_id, buffer)
+            .map_err(|e| e.map(TcpError::from))
+    }
+
+    /// State of the send buffer of a socket.
+    pub fn buffer_status(&self, socket: &TcpSocket) -> Result<BufferStatus, TcpError> {
+        self.adapter.borrow_mut().buffer_status(socket.link_id).map_err(TcpError::from)
+    }
+
+    /// Check whether a segment queued by `write_buffered` was sent.
+    pub fn is_segment_sent(&self, socket: &TcpSocket, segment_id: usize) -> Result<bool, TcpError> {
+        self.adapter
+            .borrow_mut()
+            .is_segment_sent(socket.link_id, segment_id)
+            .map_err(TcpError::from)
+    }
+
+    /// Restart the segment IDs of a socket at `1`, once all segments were sent.
+    pub fn reset_buffer(&self, socket: &TcpSocket) -> Result<(), TcpError> {
+        self.adapter.borrow_mut().reset_buffer(socket.link_id).map_err(TcpError::from)
+    }
+
+    /// Set the idle timeout of links accepted by the board's server, see
+    /// `Adapter::set_server_timeout`.
+    pub fn set_server_timeout(&self, timeout: u16) -> Result<(), AdapterError> {
+        self.adapter.borrow_mut().set_server_timeout(timeout)
+    }
+
+    /// Get the idle timeout of links accepted by the board's server.
+    pub fn get_server_timeout(&self) -> Result<u16, AdapterError> {
+        self.adapter.borrow_mut().get_server_timeout()
+    }
+
+    /// Reconcile the sockets with the board's view of its connections, see
+    /// `Adapter::reconcile_sockets`.
+    pub fn reconcile_sockets(&self) -> Result<ConnectionStatus, AdapterError> {
+        self.adapter.borrow_mut().reconcile_sockets()
+    }
 }
 
 impl<'a, Tx> IpNetworkDriver for Esp8266IpNetworkDriver<'a, Tx>
@@ -73,6 +151,178 @@ impl<'a, Tx> IpNetworkDriver for Esp8266IpNetworkDriver<'a, Tx>
 pub struct TcpSocket {
     link_id: usize,
     mode: Mode,
+    keep_alive: Option<u16>,
+}
+
+impl TcpSocket {
+    /// Set the interval, in seconds, of TCP keepalive probes, within
+    /// `KEEP_ALIVE_INTERVALS`. Applied when connecting, disabled by default.
+    ///
+    /// Keeps connections through NATs alive, which otherwise drop idle
+    /// connections silently.
+    ///
+    /// There is no option to bind to a local port, as the firmware only supports
+    /// that for UDP, and picks the local port of TCP connections itself.
+    pub fn set_keep_alive(&mut self, interval: Option<u16>) {
+        self.keep_alive = interval;
+    }
+
+    /// The interval, in seconds, of TCP keepalive probes.
+    pub fn keep_alive(&self) -> Option<u16> {
+        self.keep_alive
+    }
+
+    /// Stream data of unknown length to the socket, e.g. for line-based text
+    /// protocols, without buffering it.
+    ///
+    /// The writer borrows the network stack mutably, so it cannot be used
+    /// otherwise until the writer is dropped.
+    pub fn writer<'n, 'a, Tx>(
+        &'n mut self,
+        network: &'n mut Esp8266IpNetworkDriver<'a, Tx>,
+    ) -> SocketWriter<'n, 'a, Tx>
+        where
+            Tx: Write<u8>,
+    {
+        SocketWriter {
+            adapter: network.adapter.get_mut(),
+            link_id: self.link_id,
+            chunk: None,
+            failed: false,
+        }
+    }
+}
+
+/// Octets of a chunk, leaving room for an escape sequence.
+const CHUNK_LEN: usize = MAX_SEGMENT_LEN - 2;
+
+/// Streams data to a socket in chunks of up to `MAX_SEGMENT_LEN` octets, each
+/// terminated by `\0`. Backslashes are escaped, so the data may contain `\0`.
+///
+/// The data is sent once a chunk is full, or when flushed. Dropping the writer
+/// flushes it, ignoring errors.
+///
+/// Once writing failed, the writer fails with `TcpError::WriteError` without
+/// talking to the board, and is not flushed when dropped, as the chunk being
+/// written cannot be terminated reliably.
+pub struct SocketWriter<'n, 'a, Tx>
+    where
+        Tx: Write<u8>,
+{
+    adapter: &'n mut Adapter<'a, Tx>,
+    link_id: usize,
+    /// Octets written to the current chunk, if started.
+    chunk: Option<usize>,
+    failed: bool,
+}
+
+impl<'n, 'a, Tx> SocketWriter<'n, 'a, Tx>
+    where
+        Tx: Write<u8>,
+{
+    /// Write all of `data`, sending chunks as they fill up.
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), TcpError> {
+        self.check(|writer| writer.put_all(data))
+    }
+
+    /// Send the current chunk, if any.
+    pub fn flush(&mut self) -> Result<(), TcpError> {
+        self.check(Self::send_chunk)
+    }
+
+    /// Run `f`, unless writing failed before, and remember if it fails.
+    fn check<F>(&mut self, f: F) -> Result<(), TcpError>
+        where
+            F: FnOnce(&mut Self) -> Result<(), TcpError>,
+    {
+        if self.failed {
+            return Err(TcpError::WriteError);
+        }
+        let result = f(self);
+        self.failed = result.is_err();
+        result
+    }
+
+    fn put_all(&mut self, data: &[u8]) -> Result<(), TcpError> {
+        let mut runs = data.split(|octet| *octet == b'\\');
+        if let Some(run) = runs.next() {
+            self.put(run)?;
+        }
+        for run in runs {
+            self.put_escaped(b"\\\\")?;
+            self.put(run)?;
+        }
+        Ok(())
+    }
+
+    fn send_chunk(&mut self) -> Result<(), TcpError> {
+        if self.chunk.take().is_some() {
+            self.adapter.finish_terminated(self.link_id)?;
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, mut data: &[u8]) -> Result<(), TcpError> {
+        while !data.is_empty() {
+            let len = self.start()?;
+            let n = core::cmp::min(CHUNK_LEN - len, data.len());
+            self.adapter.write_data(&data[0..n])?;
+            self.advance(len + n)?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Write an escape sequence, which must not be split across chunks.
+    fn put_escaped(&mut self, sequence: &[u8]) -> Result<(), TcpError> {
+        let mut len = self.start()?;
+        if len + sequence.len() > CHUNK_LEN {
+            self.send_chunk()?;
+            len = self.start()?;
+        }
+        self.adapter.write_data(sequence)?;
+        self.advance(len + sequence.len())
+    }
+
+    fn start(&mut self) -> Result<usize, TcpError> {
+        match self.chunk {
+            Some(len) => Ok(len),
+            None => {
+                self.adapter.start_terminated(self.link_id)?;
+                self.chunk = Some(0);
+                Ok(0)
+            }
+        }
+    }
+
+    fn advance(&mut self, len: usize) -> Result<(), TcpError> {
+        if len < CHUNK_LEN {
+            self.chunk = Some(len);
+            Ok(())
+        } else {
+            self.send_chunk()
+        }
+    }
+}
+
+impl<'n, 'a, Tx> core::fmt::Write for SocketWriter<'n, 'a, Tx>
+    where
+        Tx: Write<u8>,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+impl<'n, 'a, Tx> Drop for SocketWriter<'n, 'a, Tx>
+    where
+        Tx: Write<u8>,
+{
+    fn drop(&mut self) {
+        if !self.failed {
+            let _ = self.flush();
+        }
+    }
 }
 
 impl Debug for TcpSocket {
@@ -92,6 +342,7 @@ impl Debug for TcpSocket {
                        }
                    },
             )
+            .field("keep_alive", &self.keep_alive)
             .finish()
     }
 }
@@ -129,7 +380,7 @@ impl From<AdapterError> for TcpError {
             AdapterError::ReadError => {
                 TcpError::ReadError
             }
-            AdapterError::InvalidSocket => {
+            AdapterError::InvalidSocket | AdapterError::ModuleReset => {
                 TcpError::SocketNotOpen
             }
             _ => {
@@ -151,6 +402,7 @@ impl<'a, Tx> TcpStack for Esp8266IpNetworkDriver<'a, Tx>
         Ok(TcpSocket {
             link_id: adapter.open()?,
             mode,
+            keep_alive: None,
         })
     }
 
@@ -161,7 +413,7 @@ impl<'a, Tx> TcpStack for Esp8266IpNetworkDriver<'a, Tx>
     ) -> Result<Self::TcpSocket, Self::Error> {
         let mut adapter = self.adapter.borrow_mut();
 
-        adapter.connect_tcp(socket.link_id, remote)?;
+        adapter.connect_tcp(socket.link_id, remote, socket.keep_alive)?;
         Ok(socket)
     }
 
@@ -173,9 +425,9 @@ impl<'a, Tx> TcpStack for Esp8266IpNetworkDriver<'a, Tx>
     fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
         let mut adapter = self.adapter.borrow_mut();
 
-        Ok(adapter
+        adapter
             .write(socket.link_id, buffer)
-            .map_err(|e| { e.map(TcpError::from) })?)
+            .map_err(|e| { e.map(TcpError::from) })
     }
 
     fn read(
@@ -16,28 +16,39 @@ pub(crate) fn ascii_to_digit(character: u8) -> Option<u8> {
 
 pub(crate) fn atoi_u8(digits: &[u8]) -> Option<u8> {
     let mut num: u8 = 0;
-    let len = digits.len();
-    for (i, digit) in digits.iter().enumerate() {
+    for digit in digits.iter() {
         let digit = ascii_to_digit(*digit)?;
-        let mut exp = 1;
-        for _ in 0..(len - i - 1) {
-            exp *= 10;
-        }
-        num += exp * digit;
+        num = num.checked_mul(10)?.checked_add(digit)?;
     }
     Some(num)
 }
 
 pub(crate) fn atoi_usize(digits: &[u8]) -> Option<usize> {
     let mut num: usize = 0;
-    let len = digits.len();
-    for (i, digit) in digits.iter().enumerate() {
+    for digit in digits.iter() {
         let digit = ascii_to_digit(*digit)? as usize;
-        let mut exp = 1;
-        for _ in 0..(len - i - 1) {
-            exp *= 10;
-        }
-        num += exp * digit;
+        num = num.checked_mul(10)?.checked_add(digit)?;
     }
     Some(num)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_atoi_u8() {
+        assert_eq!(atoi_u8(b"0"), Some(0));
+        assert_eq!(atoi_u8(b"255"), Some(255));
+        assert_eq!(atoi_u8(b"0001"), Some(1));
+        assert_eq!(atoi_u8(b"256"), None);
+        assert_eq!(atoi_u8(b"300"), None);
+        assert_eq!(atoi_u8(b"1a"), None);
+    }
+
+    #[test]
+    fn test_atoi_usize() {
+        assert_eq!(atoi_usize(b"4096"), Some(4096));
+        assert_eq!(atoi_usize(b"99999999999999999999"), None);
+    }
+}
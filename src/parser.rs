@@ -1,413 +1,671 @@
-use nom::alt;
-use nom::char;
-use nom::character::streaming::digit1;
-use nom::do_parse;
-use nom::named;
-use nom::opt;
-use nom::tag;
-use nom::take;
-use nom::take_until;
-use nom::tuple;
-use nom::IResult;
+use nom::branch::alt;
+use nom::bytes::streaming::{tag, take, take_until};
+use nom::character::streaming::{alpha1, char, digit1, space0, space1};
+use nom::combinator::opt;
+use nom::error::{Error, ErrorKind};
+use nom::sequence::tuple;
+use nom::{Err, IResult, Needed};
+use heapless::Vec;
 
 use drogue_network::addr::{
     IpAddr,
     Ipv4Addr,
+    SocketAddr,
 };
 
-use crate::protocol::{FirmwareInfo, ResolverAddresses};
+use crate::protocol::{BufferStatus, ConnectionStatus, ConnectionType, DateTime, FirmwareInfo, LinkStatus, ResolverAddresses, StationStatus, MAX_LINE_LEN, MAX_LINKS};
 use crate::protocol::IpAddresses;
 use crate::protocol::Response;
 use crate::protocol::WifiConnectionFailure;
 
 use crate::num::{atoi_u8, atoi_usize};
 
+type Parser = fn(&[u8]) -> IResult<&[u8], Response>;
+
+/// Parsers, keyed by the prefix of the first line of their response.
+const PARSERS: &[(&[u8], Parser)] = &[
+    (b"OK", ok),
+    (b"ERROR", error),
+    (b"link is not valid", error),
+    (b"AT version:", firmware_info),
+    (b"WIFI CONNECTED", wifi_connected),
+    (b"WIFI DISCONNECT", wifi_disconnect),
+    (b"WIFI GOT IP", got_ip),
+    (b"+CWJAP:", wifi_connection_failure),
+    (b"+CIPSTA_CUR:", ip_addresses),
+    (b"> ", ready_for_data),
+    (b"Recv ", received_data_to_send),
+    (b"SEND OK", send_ok),
+    (b"SEND FAIL", send_fail),
+    (b"+IPD,", data_available),
+    (b"+CIPRECVDATA,", data_received),
+    (b"+CIPDNS_CUR:", dns_resolvers),
+    (b"+CIPDOMAIN:", dns_lookup),
+    (b"DNS Fail", dns_fail),
+    (b"UNLINK", unlink_fail),
+    (b"ALREADY CONNECTED", already_connected),
+    (b"ready", ready),
+    (b"+STA_CONNECTED:", station_connected),
+    (b"+STA_DISCONNECTED:", station_disconnected),
+    (b"+DIST_STA_IP:", station_ip),
+    (b"+CIPSNTPTIME:", sntp_time),
+    (b"+timeout", ping_timeout),
+    (b"STATUS:", connection_status),
+    (b"+CIPRECVLEN:", receive_lengths),
+    (b"+CIPSTO:", server_timeout),
+];
+
 fn parse_u8(input: &[u8]) -> IResult<&[u8], u8> {
-    let (input, digits) = digit1(input)?;
-    IResult::Ok((input, atoi_u8(digits).unwrap()))
+    let (remainder, digits) = digit1(input)?;
+    match atoi_u8(digits) {
+        Some(num) => Ok((remainder, num)),
+        None => Err(Err::Error(Error::new(input, ErrorKind::Digit))),
+    }
 }
 
 fn parse_usize(input: &[u8]) -> IResult<&[u8], usize> {
-    let (input, digits) = digit1(input)?;
-    let num = atoi_usize(digits).unwrap();
-    IResult::Ok((input, num))
-}
-
-#[rustfmt::skip]
-named!(
-    crlf,
-    tag!("\r\n")
-);
-
-#[rustfmt::skip]
-named!(
-    pub ok<Response>,
-    do_parse!(
-        tuple!(
-            opt!(crlf),
-            opt!(crlf),
-            tag!("OK"),
-            crlf
-        ) >>
-        (
-            Response::Ok
-        )
-    )
-);
-
-named!(
-    pub reason<()>,
-    do_parse!(
-        // use "!alt" once we identified an additional reason
-        link_invalid
-        >> ()
-    )
-);
-
-named!(
-    pub link_invalid<()>,
-    do_parse!(
-        tag!("link is not valid") >> ()
-    )
-);
-
-named!(
-    pub error<Response>,
-    do_parse!(
-        opt!(reason) >>
-        opt!(crlf) >>
-        opt!(crlf) >>
-        tag!("ERROR") >>
-        crlf >>
-        (
-            Response::Error
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub wifi_connected<Response>,
-    do_parse!(
-        tuple!(
-            tag!("WIFI CONNECTED"),
-            crlf
-        ) >>
-        (
-            Response::WifiConnected
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub wifi_disconnect<Response>,
-    do_parse!(
-        tuple!(
-            tag!("WIFI DISCONNECT"),
-            crlf
-        ) >>
-        (
-            Response::WifiDisconnect
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub got_ip<Response>,
-    do_parse!(
-        tuple!(
-            tag!("WIFI GOT IP"),
-            crlf
-        ) >>
-        (
-            Response::GotIp
-        )
-    )
-);
-
-named!(
-    pub wifi_connection_failure<Response>,
-    do_parse!(
-        tag!("+CWJAP:") >>
-        code: parse_u8 >>
-        crlf >>
-        crlf >>
-        tag!("FAIL") >>
-        crlf >>
-        (
-            Response::WifiConnectionFailure(WifiConnectionFailure::from(code))
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub firmware_info<Response>,
-    do_parse!(
-        tag!("AT version:") >>
-        major: parse_u8 >>
-        tag!(".") >>
-        minor: parse_u8 >>
-        tag!(".") >>
-        patch: parse_u8 >>
-        tag!(".") >>
-        build: parse_u8 >>
-        take_until!("OK") >>
-        ok >>
-        (
-            Response::FirmwareInfo(FirmwareInfo{major, minor, patch, build})
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    ip_addr<Ipv4Addr>,
-    do_parse!(
-        a: parse_u8 >>
-        char!('.') >>
-        b: parse_u8 >>
-        char!('.') >>
-        c: parse_u8 >>
-        char!('.') >>
-        d: parse_u8 >>
-        (
-            Ipv4Addr::new(a, b, c, d)
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub ip_addresses<Response>,
-    do_parse!(
-        tag!("+CIPSTA_CUR:ip:\"") >>
-        ip: ip_addr >>
-        tag!("\"") >>
-        crlf >>
-        tag!("+CIPSTA_CUR:gateway:\"") >>
-        gateway: ip_addr >>
-        tag!("\"") >>
-        crlf >>
-        tag!("+CIPSTA_CUR:netmask:\"") >>
-        netmask: ip_addr >>
-        tag!("\"") >>
-        crlf >>
-        crlf >>
-        ok >>
-        (
-            Response::IpAddresses(
-                IpAddresses {
-                    ip,
-                    gateway,
-                    netmask,
-                }
-            )
-        )
-    )
-);
-
-#[rustfmt::skip]
-named!(
-    pub connect<Response>,
-    do_parse!(
-        link_id: parse_u8 >>
-        tag!(",CONNECT") >>
-        crlf >>
-        ok >>
-        (
-            Response::Connect(link_id as usize)
-        )
-    )
-);
-
-named!(
-    pub ready_for_data<Response>,
-    do_parse!(
-        tag!("> ") >>
-        (
-            Response::ReadyForData
-        )
-    )
-);
-
-named!(
-    pub received_data_to_send<Response>,
-    do_parse!(
-        opt!( crlf ) >>
-        tag!("Recv ") >>
-        len: parse_usize >>
-        tag!(" bytes") >>
-        crlf >>
-        (
-            Response::ReceivedDataToSend(len)
-        )
-    )
-);
-
-named!(
-    pub send_ok<Response>,
-    do_parse!(
-        opt!( crlf ) >>
-        tag!("SEND OK") >>
-        crlf >>
-        (
-            Response::SendOk
-        )
-    )
-);
-
-named!(
-    pub send_fail<Response>,
-    do_parse!(
-        opt!( crlf ) >>
-        tag!("SEND FAIL") >>
-        crlf >>
-        (
-            Response::SendFail
-        )
-    )
-);
-
-named!(
-    pub data_available<Response>,
-    do_parse!(
-        opt!( crlf ) >>
-        tag!( "+IPD,") >>
-        link_id: parse_usize >>
-        char!(',') >>
-        len: parse_usize >>
-        crlf >>
-        (
-            Response::DataAvailable {link_id, len }
-        )
-    )
-);
-
-named!(
-    pub closed<Response>,
-    do_parse!(
-        opt!(crlf) >>
-        link_id: parse_usize >>
-        tag!(",CLOSED") >>
-        crlf >>
-        (
-            Response::Closed(link_id)
-        )
-    )
-);
-
-named!(
-    pub data_received<Response>,
-    do_parse!(
-        opt!(tag!("\r")) >>
-        opt!(tag!("\n")) >>
-        tag!("+CIPRECVDATA,") >>
-        len: parse_usize >>
-        char!(':') >>
-        data: take!(len) >>
-        crlf >>
-        ok >>
-        ( {
-            let mut buf = [0; crate::BUFFER_LEN];
-            for (i, b) in data.iter().enumerate() {
-                //log::info!( "copy {} @ {}", *b as char, i);
-                buf[i] = *b;
-            }
-            //log::info!("------------> onwards {:?}", buf);
-            Response::DataReceived(buf, len)
-        } )
-    )
-);
-
-named!(
-    pub dns_resolvers<Response>,
-    do_parse!(
-        tag!("+CIPDNS_CUR:") >>
-        ns1: ip_addr >> crlf >>
-        ns2: opt!(
-            do_parse!(
-                tag!("+CIPDNS_CUR:") >>
-                ns2: ip_addr >> crlf >>
-                (
-                    ns2
-                )
-            )
-        ) >>
-        ok >>
-        (
-            Response::Resolvers(
-                ResolverAddresses{
-                    resolver1: ns1,
-                    resolver2: ns2, /* ns2 is an Option */
-                }
-            )
-        )
-    )
-);
-
-named!(
-    pub dns_lookup<Response>,
-    do_parse!(
-        tag!("+CIPDOMAIN:") >>
-        ip_addr: ip_addr >>
-        crlf >>
-        ok >>
-        (
-            Response::IpAddress(IpAddr::V4(ip_addr))
-        )
-    )
-);
-
-named!(
-    pub dns_fail<Response>,
-    do_parse!(
-        tag!("DNS Fail") >>
-        crlf >>
-        error >>
-        (
-            Response::DnsFail
-        )
-    )
-);
-
-named!(
-    pub unlink_fail<Response>,
-    do_parse!(
-        opt!(crlf) >>
-        tag!("UNLINK") >>
-        crlf >>
-        error >>
-        (
-            Response::UnlinkFail
-        )
-    )
-);
-
-named!(
-    pub parse<Response>,
-    alt!(
-          ok
-        | error
-        | firmware_info
-        | wifi_connected
-        | wifi_disconnect
-        | wifi_connection_failure
-        | got_ip
-        | ip_addresses
-        | connect
-        | closed
-        | ready_for_data
-        | received_data_to_send
-        | send_ok
-        | send_fail
-        | data_available
-        | data_received
-        | dns_resolvers
-        | dns_lookup
-        | dns_fail
-        | unlink_fail
-    )
-);
+    let (remainder, digits) = digit1(input)?;
+    match atoi_usize(digits) {
+        Some(num) => Ok((remainder, num)),
+        None => Err(Err::Error(Error::new(input, ErrorKind::Digit))),
+    }
+}
+
+fn parse_u16(input: &[u8]) -> IResult<&[u8], u16> {
+    let (remainder, port) = parse_usize(input)?;
+    if port > u16::MAX as usize {
+        return Err(Err::Error(Error::new(input, ErrorKind::TooLarge)));
+    }
+    Ok((remainder, port as u16))
+}
+
+/// Link ID of a connection, which must be within the socket pool.
+fn parse_link_id(input: &[u8]) -> IResult<&[u8], usize> {
+    let (remainder, link_id) = parse_usize(input)?;
+    if link_id >= MAX_LINKS {
+        return Err(Err::Error(Error::new(input, ErrorKind::TooLarge)));
+    }
+    Ok((remainder, link_id))
+}
+
+/// Length of a data frame, which must fit into a `DataReceived` response.
+fn parse_data_len(input: &[u8]) -> IResult<&[u8], usize> {
+    let (remainder, len) = parse_usize(input)?;
+    if len > crate::BUFFER_LEN {
+        return Err(Err::Error(Error::new(input, ErrorKind::TooLarge)));
+    }
+    Ok((remainder, len))
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Quoted MAC address, e.g. `"5c:cf:7f:0a:1b:2c"`.
+fn mac_addr(input: &[u8]) -> IResult<&[u8], [u8; 6]> {
+    let (mut remainder, _) = char('"')(input)?;
+    let mut mac = [0; 6];
+    for (i, octet) in mac.iter_mut().enumerate() {
+        if i > 0 {
+            remainder = char(':')(remainder)?.0;
+        }
+        let (r, digits) = take(2usize)(remainder)?;
+        *octet = match (hex_digit(digits[0]), hex_digit(digits[1])) {
+            (Some(high), Some(low)) => high << 4 | low,
+            _ => return Err(Err::Error(Error::new(remainder, ErrorKind::HexDigit))),
+        };
+        remainder = r;
+    }
+    let (remainder, _) = char('"')(remainder)?;
+    Ok((remainder, mac))
+}
+
+fn crlf(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    tag("\r\n")(input)
+}
+
+pub fn ok(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((
+        opt(crlf),
+        opt(crlf),
+        tag("OK"),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::Ok))
+}
+
+fn link_invalid(input: &[u8]) -> IResult<&[u8], ()> {
+    let (input, _) = tag("link is not valid")(input)?;
+    Ok((input, ()))
+}
+
+fn reason(input: &[u8]) -> IResult<&[u8], ()> {
+    // use "alt" once we identified an additional reason
+    link_invalid(input)
+}
+
+pub fn error(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((
+        opt(reason),
+        opt(crlf),
+        opt(crlf),
+        tag("ERROR"),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::Error))
+}
+
+pub fn ready(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("ready"), crlf))(input)?;
+    Ok((input, Response::Ready))
+}
+
+pub fn wifi_connected(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("WIFI CONNECTED"), crlf))(input)?;
+    Ok((input, Response::WifiConnected))
+}
+
+pub fn wifi_disconnect(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("WIFI DISCONNECT"), crlf))(input)?;
+    Ok((input, Response::WifiDisconnect))
+}
+
+pub fn got_ip(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("WIFI GOT IP"), crlf))(input)?;
+    Ok((input, Response::GotIp))
+}
+
+pub fn wifi_connection_failure(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, code, _, _, _, _)) = tuple((
+        tag("+CWJAP:"),
+        parse_u8,
+        crlf,
+        crlf,
+        tag("FAIL"),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::WifiConnectionFailure(WifiConnectionFailure::from(code))))
+}
+
+pub fn firmware_info(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, major, _, minor, _, patch, _, build, _, _)) = tuple((
+        tag("AT version:"),
+        parse_u8,
+        tag("."),
+        parse_u8,
+        tag("."),
+        parse_u8,
+        tag("."),
+        parse_u8,
+        take_until("OK"),
+        ok,
+    ))(input)?;
+    Ok((input, Response::FirmwareInfo(FirmwareInfo { major, minor, patch, build })))
+}
+
+fn ip_addr(input: &[u8]) -> IResult<&[u8], Ipv4Addr> {
+    let (input, (a, _, b, _, c, _, d)) = tuple((
+        parse_u8,
+        char('.'),
+        parse_u8,
+        char('.'),
+        parse_u8,
+        char('.'),
+        parse_u8,
+    ))(input)?;
+    Ok((input, Ipv4Addr::new(a, b, c, d)))
+}
+
+pub fn ip_addresses(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, ip, _, _)) = tuple((
+        tag("+CIPSTA_CUR:ip:\""),
+        ip_addr,
+        tag("\""),
+        crlf,
+    ))(input)?;
+    let (input, (_, gateway, _, _)) = tuple((
+        tag("+CIPSTA_CUR:gateway:\""),
+        ip_addr,
+        tag("\""),
+        crlf,
+    ))(input)?;
+    let (input, (_, netmask, _, _, _, _)) = tuple((
+        tag("+CIPSTA_CUR:netmask:\""),
+        ip_addr,
+        tag("\""),
+        crlf,
+        crlf,
+        ok,
+    ))(input)?;
+    Ok((input, Response::IpAddresses(IpAddresses { ip, gateway, netmask })))
+}
+
+pub fn connect(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (link_id, _, _, _)) = tuple((
+        parse_link_id,
+        tag(",CONNECT"),
+        crlf,
+        ok,
+    ))(input)?;
+    Ok((input, Response::Connect(link_id)))
+}
+
+pub fn ready_for_data(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tag("> ")(input)?;
+    Ok((input, Response::ReadyForData))
+}
+
+pub fn received_data_to_send(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, len, _, _)) = tuple((
+        tag("Recv "),
+        parse_usize,
+        tag(" bytes"),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::ReceivedDataToSend(len)))
+}
+
+pub fn send_ok(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SEND OK"), crlf))(input)?;
+    Ok((input, Response::SendOk))
+}
+
+pub fn send_fail(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("SEND FAIL"), crlf))(input)?;
+    Ok((input, Response::SendFail))
+}
+
+/// Sender of received data, e.g. `,"192.168.1.1",8080`, quoted or not.
+fn remote_addr(input: &[u8]) -> IResult<&[u8], SocketAddr> {
+    let (input, (_, _, ip, _, _, port)) = tuple((
+        char(','),
+        opt(char('"')),
+        ip_addr,
+        opt(char('"')),
+        char(','),
+        parse_u16,
+    ))(input)?;
+    Ok((input, SocketAddr::new(IpAddr::V4(ip), port)))
+}
+
+pub fn data_available(input: &[u8]) -> IResult<&[u8], Response> {
+    // the sender is reported again when reading the data
+    let (input, (_, link_id, _, len, _, _)) = tuple((
+        tag("+IPD,"),
+        parse_link_id,
+        char(','),
+        parse_usize,
+        opt(remote_addr),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::DataAvailable { link_id, len }))
+}
+
+pub fn closed(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (link_id, _, _)) = tuple((
+        parse_link_id,
+        tag(",CLOSED"),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::Closed(link_id)))
+}
+
+/// Asynchronous result of a segment of the send buffer, e.g. `0,1,SEND OK`.
+pub fn segment_sent(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (link_id, _, segment_id, _, result, _)) = tuple((
+        parse_link_id,
+        char(','),
+        parse_usize,
+        char(','),
+        alt((tag("SEND OK"), tag("SEND FAIL"))),
+        crlf,
+    ))(input)?;
+    if result == b"SEND OK" {
+        Ok((input, Response::SegmentSent { link_id, segment_id }))
+    } else {
+        Ok((input, Response::SegmentFailed { link_id, segment_id }))
+    }
+}
+
+pub fn segment_queued(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (segment_id, _, sent_segment_id, _, _)) = tuple((
+        parse_usize,
+        char(','),
+        parse_usize,
+        crlf,
+        ok,
+    ))(input)?;
+    Ok((input, Response::SegmentQueued { segment_id, sent_segment_id }))
+}
+
+pub fn segment_status(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (link_id, _, segment_id, _, sent, _, _)) = tuple((
+        parse_link_id,
+        char(','),
+        parse_usize,
+        char(','),
+        alt((char('0'), char('1'))),
+        crlf,
+        ok,
+    ))(input)?;
+    Ok((input, Response::SegmentStatus { link_id, segment_id, sent: sent == '1' }))
+}
+
+/// Unlike the replies to `AT+CIPCHECKSEQ`, the reply to `AT+CIPBUFSTATUS` does
+/// not echo the link ID, even with multiple connections enabled, so the link is
+/// only known from the query.
+pub fn buffer_status(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (next_segment_id, _, sent_segment_id, _, acknowledged_segment_id, _, free, _, queued, _, _)) =
+        tuple((
+            parse_usize,
+            char(','),
+            parse_usize,
+            char(','),
+            parse_usize,
+            char(','),
+            parse_usize,
+            char(','),
+            parse_usize,
+            crlf,
+            ok,
+        ))(input)?;
+    Ok((input, Response::BufferStatus(BufferStatus {
+        next_segment_id,
+        sent_segment_id,
+        acknowledged_segment_id,
+        free,
+        queued,
+    })))
+}
+
+pub fn data_received(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, len, remote, _)) = tuple((
+        tag("+CIPRECVDATA,"),
+        parse_data_len,
+        opt(remote_addr),
+        char(':'),
+    ))(input)?;
+    let (input, (data, _, _)) = tuple((take(len), crlf, ok))(input)?;
+
+    let mut buf = [0; crate::BUFFER_LEN];
+    buf[0..len].copy_from_slice(data);
+    Ok((input, Response::DataReceived(buf, len, remote)))
+}
+
+pub fn dns_resolvers(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, resolver1, _)) = tuple((
+        tag("+CIPDNS_CUR:"),
+        ip_addr,
+        crlf,
+    ))(input)?;
+    let (input, resolver2) = opt(tuple((
+        tag("+CIPDNS_CUR:"),
+        ip_addr,
+        crlf,
+    )))(input)?;
+    let (input, _) = ok(input)?;
+    Ok((
+        input,
+        Response::Resolvers(ResolverAddresses {
+            resolver1,
+            resolver2: resolver2.map(|(_, ns2, _)| ns2),
+        }),
+    ))
+}
+
+pub fn dns_lookup(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, ip_addr, _, _)) = tuple((
+        tag("+CIPDOMAIN:"),
+        ip_addr,
+        crlf,
+        ok,
+    ))(input)?;
+    Ok((input, Response::IpAddress(IpAddr::V4(ip_addr))))
+}
+
+pub fn dns_fail(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("DNS Fail"), crlf, error))(input)?;
+    Ok((input, Response::DnsFail))
+}
+
+pub fn unlink_fail(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("UNLINK"), crlf, error))(input)?;
+    Ok((input, Response::UnlinkFail))
+}
+
+pub fn already_connected(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("ALREADY CONNECTED"), crlf, error))(input)?;
+    Ok((input, Response::AlreadyConnected))
+}
+
+pub fn station_connected(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, mac, _)) = tuple((tag("+STA_CONNECTED:"), mac_addr, crlf))(input)?;
+    Ok((input, Response::StationConnected(mac)))
+}
+
+pub fn station_disconnected(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, mac, _)) = tuple((tag("+STA_DISCONNECTED:"), mac_addr, crlf))(input)?;
+    Ok((input, Response::StationDisconnected(mac)))
+}
+
+pub fn station_ip(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, mac, _, _, ip, _, _)) = tuple((
+        tag("+DIST_STA_IP:"),
+        mac_addr,
+        tag(","),
+        char('"'),
+        ip_addr,
+        char('"'),
+        crlf,
+    ))(input)?;
+    Ok((input, Response::StationIp(mac, ip)))
+}
+
+fn month(input: &[u8]) -> IResult<&[u8], u8> {
+    const MONTHS: [&[u8]; 12] = [
+        b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun",
+        b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+    ];
+    let (remainder, name) = alpha1(input)?;
+    match MONTHS.iter().position(|month| *month == name) {
+        Some(index) => Ok((remainder, index as u8 + 1)),
+        None => Err(Err::Error(Error::new(input, ErrorKind::Tag))),
+    }
+}
+
+/// Time in the format of `asctime`, e.g. `Thu Aug 04 14:48:05 2016`.
+pub fn sntp_time(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, _, _, month, _, day, _, hour, _, minute, _, second, _, year, _, _, _)) = tuple((
+        tag("+CIPSNTPTIME:"),
+        alpha1,
+        space1,
+        month,
+        space1,
+        parse_u8,
+        space1,
+        parse_u8,
+        char(':'),
+        parse_u8,
+        char(':'),
+        parse_u8,
+        space1,
+        parse_usize,
+        space0,
+        crlf,
+        ok,
+    ))(input)?;
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year > 9999 {
+        return Err(Err::Error(Error::new(input, ErrorKind::Verify)));
+    }
+    Ok((input, Response::SntpTime(DateTime {
+        year: year as u16,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    })))
+}
+
+pub fn ping_reply(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, time, _, _)) = tuple((tag("+"), parse_usize, crlf, ok))(input)?;
+    Ok((input, Response::PingReply(time)))
+}
+
+pub fn ping_timeout(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, _) = tuple((tag("+timeout"), crlf, error))(input)?;
+    Ok((input, Response::PingTimeout))
+}
+
+
+fn connection_type(input: &[u8]) -> IResult<&[u8], ConnectionType> {
+    alt((
+        |input| tag("\"TCP\"")(input).map(|(input, _)| (input, ConnectionType::TCP)),
+        |input| tag("\"UDP\"")(input).map(|(input, _)| (input, ConnectionType::UDP)),
+    ))(input)
+}
+
+/// A link, e.g. `+CIPSTATUS:0,"TCP","192.168.1.1",8080,40000,0`.
+fn link_status(input: &[u8]) -> IResult<&[u8], LinkStatus> {
+    let (input, (_, link_id, _, connection_type, _, _, ip, _, _, remote_port, _, local_port, _, server, _)) =
+        tuple((
+            tag("+CIPSTATUS:"),
+            parse_link_id,
+            char(','),
+            connection_type,
+            char(','),
+            char('"'),
+            ip_addr,
+            char('"'),
+            char(','),
+            parse_u16,
+            char(','),
+            parse_u16,
+            char(','),
+            alt((char('0'), char('1'))),
+            crlf,
+        ))(input)?;
+    Ok((input, LinkStatus {
+        link_id,
+        connection_type,
+        remote: SocketAddr::new(IpAddr::V4(ip), remote_port),
+        local_port,
+        server: server == '1',
+    }))
+}
+
+/// State of the station interface, followed by a line per open link.
+pub fn connection_status(input: &[u8]) -> IResult<&[u8], Response> {
+    let (mut input, (_, code, _)) = tuple((tag("STATUS:"), parse_u8, crlf))(input)?;
+    let mut status = ConnectionStatus {
+        status: StationStatus::from(code),
+        links: Vec::new(),
+    };
+    while let (remainder, Some(link)) = opt(link_status)(input)? {
+        if status.links.iter().any(|l| l.link_id == link.link_id) || status.links.push(link).is_err() {
+            return Err(Err::Error(Error::new(input, ErrorKind::Verify)));
+        }
+        input = remainder;
+    }
+    let (input, _) = ok(input)?;
+    Ok((input, Response::ConnectionStatus(status)))
+}
+
+/// Octets pending on a link, empty or `-1` if the link is not connected.
+fn receive_length(input: &[u8]) -> IResult<&[u8], usize> {
+    if let (remainder, Some(_)) = opt(tag("-1"))(input)? {
+        return Ok((remainder, 0));
+    }
+    let (remainder, len) = opt(parse_usize)(input)?;
+    Ok((remainder, len.unwrap_or_default()))
+}
+
+/// Octets pending per link, e.g. `+CIPRECVLEN:0,12,,,`.
+pub fn receive_lengths(input: &[u8]) -> IResult<&[u8], Response> {
+    let (mut input, _) = tag("+CIPRECVLEN:")(input)?;
+    let mut lengths = [0; MAX_LINKS];
+    for (link_id, len) in lengths.iter_mut().enumerate() {
+        if link_id > 0 {
+            input = char(',')(input)?.0;
+        }
+        let (remainder, l) = receive_length(input)?;
+        *len = l;
+        input = remainder;
+    }
+    let (input, _) = tuple((crlf, ok))(input)?;
+    Ok((input, Response::ReceiveLengths(lengths)))
+}
+
+pub fn server_timeout(input: &[u8]) -> IResult<&[u8], Response> {
+    let (input, (_, timeout, _, _)) = tuple((tag("+CIPSTO:"), parse_u16, crlf, ok))(input)?;
+    Ok((input, Response::ServerTimeout(timeout)))
+}
+
+/// Parse the next response from the input.
+///
+/// Leading line breaks are skipped, then the response is selected by the
+/// prefix of its first line. Returns `Err::Incomplete` while more input is
+/// required to decide, and `Err::Error` if the input cannot be parsed.
+pub fn parse(input: &[u8]) -> IResult<&[u8], Response> {
+    let input = match input.iter().position(|b| !matches!(b, b'\r' | b'\n')) {
+        Some(start) => &input[start..],
+        None => return Err(Err::Incomplete(Needed::Unknown)),
+    };
+
+    if input[0] == b'+' && matches!(input.get(1), Some(c) if c.is_ascii_digit()) {
+        return ping_reply(input);
+    }
+
+    if input[0].is_ascii_digit() {
+        return match alt((
+            connect,
+            closed,
+            segment_sent,
+            segment_queued,
+            segment_status,
+            buffer_status,
+        ))(input) {
+            Err(Err::Error(_)) => line(input),
+            result => result,
+        };
+    }
+
+    let mut incomplete = false;
+    for (prefix, parser) in PARSERS.iter() {
+        if input.starts_with(prefix) {
+            return parser(input);
+        }
+        if prefix.starts_with(input) {
+            incomplete = true;
+        }
+    }
+
+    if incomplete {
+        Err(Err::Incomplete(Needed::Unknown))
+    } else {
+        line(input)
+    }
+}
+
+/// Any other line, up to `MAX_LINE_LEN` octets.
+pub fn line(input: &[u8]) -> IResult<&[u8], Response> {
+    let end = match input.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if input.len() > MAX_LINE_LEN + 1 => {
+            return Err(Err::Error(Error::new(input, ErrorKind::TooLarge)));
+        }
+        None => return Err(Err::Incomplete(Needed::Unknown)),
+    };
+    if end > MAX_LINE_LEN {
+        return Err(Err::Error(Error::new(input, ErrorKind::TooLarge)));
+    }
+
+    let mut line = [0; MAX_LINE_LEN];
+    line[0..end].copy_from_slice(&input[0..end]);
+    Ok((&input[end + 2..], Response::Line(line, end)))
+}
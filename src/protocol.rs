@@ -3,26 +3,67 @@ use core::fmt::{Debug, Write};
 use drogue_network::addr::{IpAddr, Ipv4Addr, SocketAddr};
 use heapless::{
     String,
+    Vec,
     consts::{
-        U128,
+        U5,
+        U8,
+        U256,
     }
 };
 
-#[derive(Debug)]
+/// Number of simultaneous connections supported by the firmware, link IDs
+/// range from `0` to `MAX_LINKS - 1`.
+pub const MAX_LINKS: usize = 5;
+
+/// Maximum length, in bytes, of an access-point SSID.
+pub const MAX_SSID_LEN: usize = 32;
+/// Maximum length, in bytes, of an access-point password.
+pub const MAX_PASSWORD_LEN: usize = 64;
+/// Maximum length, in bytes, of a hostname to be resolved by the board.
+pub const MAX_HOSTNAME_LEN: usize = 64;
+/// Maximum number of SNTP servers the board may be configured with.
+pub const MAX_SNTP_SERVERS: usize = 3;
+/// Range of timezones, in hours from UTC, supported by the board's SNTP client.
+pub const SNTP_TIMEZONES: core::ops::RangeInclusive<i8> = -11..=13;
+/// Range of the TCP keepalive interval, in seconds, supported by the board.
+pub const KEEP_ALIVE_INTERVALS: core::ops::RangeInclusive<u16> = 1..=7200;
+/// Maximum length, in bytes, of a segment queued into the send buffer.
+pub const MAX_SEGMENT_LEN: usize = 2048;
+/// Maximum idle timeout, in seconds, of links accepted by the board's server.
+pub const MAX_SERVER_TIMEOUT: u16 = 7200;
+/// Maximum length, in bytes, of a raw command.
+pub const MAX_RAW_COMMAND_LEN: usize = 256;
+/// Maximum length, in bytes, of a response line not modelled by `Response`.
+pub const MAX_LINE_LEN: usize = 128;
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ResolverAddresses {
+    #[cfg_attr(feature = "defmt", defmt(Display2Format))]
     pub resolver1: Ipv4Addr,
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
     pub resolver2: Option<Ipv4Addr>,
 }
 
 /// Type of socket connection.
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectionType {
     TCP,
     UDP,
 }
 
+/// Options of a connection, applied when it is started.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionOptions {
+    /// Interval, in seconds, of TCP keepalive probes. Only supported for TCP.
+    pub keep_alive: Option<u16>,
+}
+
 /// Mode of the Wi-Fi stack
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WiFiMode {
     /// Station mode, aka client
     Station,
@@ -32,101 +73,608 @@ pub enum WiFiMode {
     SoftAccessPointAndStation,
 }
 
+/// Number of data bits per UART frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+/// Number of stop bits per UART frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StopBits {
+    One,
+    OneAndHalf,
+    Two,
+}
+
+/// Parity of UART frames.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Hardware flow control on the UART.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlowControl {
+    None,
+    /// The board drives RTS, to stop the MCU from sending.
+    Rts,
+    /// The board honours CTS, to be stopped from sending by the MCU.
+    Cts,
+    /// Both RTS and CTS.
+    RtsCts,
+}
+
+/// Configuration of the board's UART.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UartConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub stop_bits: StopBits,
+    pub parity: Parity,
+    pub flow_control: FlowControl,
+}
+
+impl UartConfig {
+    /// Lowest baud rate supported by the board.
+    pub const MIN_BAUD_RATE: u32 = 110;
+    /// Highest baud rate supported by the board.
+    pub const MAX_BAUD_RATE: u32 = 4_608_000;
+
+    /// 8-N-1 without flow control, at the given baud rate.
+    pub fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+impl Default for UartConfig {
+    /// The configuration of a board fresh from the factory, 115,200 bps 8-N-1.
+    fn default() -> Self {
+        Self::new(115_200)
+    }
+}
+
+/// Reasons a command argument cannot be sent to the board.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ArgumentError {
+    /// The SSID is empty or longer than `MAX_SSID_LEN`.
+    InvalidSsid,
+    /// The password is longer than `MAX_PASSWORD_LEN`.
+    InvalidPassword,
+    /// The hostname is empty or longer than `MAX_HOSTNAME_LEN`.
+    InvalidHostname,
+    /// The baud rate is outside the range supported by the board.
+    InvalidBaudRate,
+    /// The raw command does not start with `AT`, contains a line ending or is
+    /// longer than `MAX_RAW_COMMAND_LEN`.
+    InvalidRawCommand,
+    /// The timezone is outside of `SNTP_TIMEZONES`.
+    InvalidTimezone,
+    /// More than `MAX_SNTP_SERVERS` servers, or a server name is empty or longer
+    /// than `MAX_HOSTNAME_LEN`.
+    InvalidSntpServers,
+    /// The keepalive interval is outside of `KEEP_ALIVE_INTERVALS`.
+    InvalidKeepAlive,
+    /// The server timeout is longer than `MAX_SERVER_TIMEOUT`.
+    InvalidServerTimeout,
+    /// The segment is empty or longer than `MAX_SEGMENT_LEN`.
+    InvalidSegmentLength,
+}
+
+/// Reasons a command cannot be serialized for the board.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandError {
+    /// An argument is outside the limits of the firmware.
+    InvalidArgument(ArgumentError),
+    /// The command requires a feature the firmware does not support, such as IPv6.
+    Unsupported,
+    /// The serialized command does not fit into the buffer.
+    Overflow,
+}
+
 /// Commands to be sent to the ESP board.
-#[derive(Debug)]
+///
+/// Secrets, such as passwords and the arguments of raw commands, are redacted
+/// from the `Debug` output, so commands may be logged safely.
 pub enum Command<'a> {
+    Test,
+    DisableEcho,
+    EnableMux,
+    SetPassiveRecvMode,
+    /// Report the remote address along with received data.
+    SetDataInfo(bool),
     QueryFirmwareInfo,
     SetMode(WiFiMode),
-    JoinAp { ssid: &'a str, password: &'a str },
+    JoinAp { ssid: &'a str, password: &'a str, bssid: Option<[u8; 6]> },
     QueryIpAddress,
-    StartConnection(usize, ConnectionType, SocketAddr),
+    QueryConnectionStatus,
+    StartConnection(usize, ConnectionType, SocketAddr, ConnectionOptions),
     CloseConnection(usize),
     Send { link_id: usize, len: usize },
     Receive { link_id: usize, len: usize },
+    /// Send up to `len` octets, or until the data is terminated by `\0`.
+    SendTerminated { link_id: usize, len: usize },
+    /// Queue a segment into the board's send buffer of a TCP link.
+    SendBuffered { link_id: usize, len: usize },
+    QueryBufferStatus(usize),
+    /// Check whether a segment of the send buffer was sent.
+    CheckSegment { link_id: usize, segment_id: usize },
+    ResetBuffer(usize),
+    QueryReceiveLengths,
+    /// Idle timeout, in seconds, of links accepted by the board's server.
+    SetServerTimeout(u16),
+    QueryServerTimeout,
     QueryDnsResolvers,
     SetDnsResolvers(ResolverAddresses),
     GetHostByName{ hostname: &'a str},
+    SetUart { config: UartConfig, persist: bool },
+    SetSntpConfig { enable: bool, timezone: i8, servers: &'a [&'a str] },
+    QuerySntpTime,
+    /// Ping a host, given by IP address or hostname.
+    Ping { host: &'a str },
+    /// A command line not modelled by this crate, without the line ending.
+    Raw(&'a str),
 }
 
 impl<'a> Command<'a> {
-    pub fn as_bytes(&self) -> String<U128> {
+    /// Check the command's arguments against the limits of the firmware.
+    pub fn validate(&self) -> Result<(), CommandError> {
         match self {
-            Command::QueryFirmwareInfo => String::from("AT+GMR"),
-            Command::QueryIpAddress => String::from("AT+CIPSTA_CUR?"),
+            Command::JoinAp { ssid, password, .. } => {
+                if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidSsid));
+                }
+                if password.len() > MAX_PASSWORD_LEN {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidPassword));
+                }
+                Ok(())
+            }
+            Command::GetHostByName { hostname } | Command::Ping { host: hostname } => {
+                if hostname.is_empty() || hostname.len() > MAX_HOSTNAME_LEN {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidHostname));
+                }
+                Ok(())
+            }
+            Command::SetUart { config, .. } => {
+                if config.baud_rate < UartConfig::MIN_BAUD_RATE
+                    || config.baud_rate > UartConfig::MAX_BAUD_RATE
+                {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidBaudRate));
+                }
+                Ok(())
+            }
+            Command::StartConnection(_, connection_type, socket_addr, options) => {
+                if let IpAddr::V6(_) = socket_addr.ip() {
+                    return Err(CommandError::Unsupported);
+                }
+                match (connection_type, options) {
+                    (ConnectionType::UDP, ConnectionOptions { keep_alive: Some(_) }) => {
+                        Err(CommandError::Unsupported)
+                    }
+                    (_, ConnectionOptions { keep_alive: Some(interval) })
+                        if !KEEP_ALIVE_INTERVALS.contains(interval) =>
+                    {
+                        Err(CommandError::InvalidArgument(ArgumentError::InvalidKeepAlive))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Command::SendBuffered { len, .. } | Command::SendTerminated { len, .. } => {
+                if *len == 0 || *len > MAX_SEGMENT_LEN {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidSegmentLength));
+                }
+                Ok(())
+            }
+            Command::SetServerTimeout(timeout) => {
+                if *timeout > MAX_SERVER_TIMEOUT {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidServerTimeout));
+                }
+                Ok(())
+            }
+            Command::SetSntpConfig { enable: true, timezone, servers } => {
+                if !SNTP_TIMEZONES.contains(timezone) {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidTimezone));
+                }
+                if servers.len() > MAX_SNTP_SERVERS
+                    || servers.iter().any(|s| s.is_empty() || s.len() > MAX_HOSTNAME_LEN)
+                {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidSntpServers));
+                }
+                Ok(())
+            }
+            Command::Raw(command) => {
+                if !command.starts_with("AT")
+                    || command.len() > MAX_RAW_COMMAND_LEN
+                    || command.contains(['\r', '\n'])
+                {
+                    return Err(CommandError::InvalidArgument(ArgumentError::InvalidRawCommand));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Serialize the command into a buffer, without the trailing line ending.
+    pub fn as_bytes(&self) -> Result<String<U256>, CommandError> {
+        self.validate()?;
+        let mut s = String::new();
+        self.write_to(&mut s).map_err(|_| CommandError::Overflow)?;
+        Ok(s)
+    }
+
+    /// Stream the command to a writer, without the trailing line ending.
+    ///
+    /// The command must have been validated beforehand, an invalid command
+    /// only fails once the writer has already received part of it.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self {
+            Command::Test => w.write_str("AT"),
+            Command::DisableEcho => w.write_str("ATE0"),
+            Command::EnableMux => w.write_str("AT+CIPMUX=1"),
+            Command::SetPassiveRecvMode => w.write_str("AT+CIPRECVMODE=1"),
+            Command::SetDataInfo(enable) => write!(w, "AT+CIPDINFO={}", *enable as u8),
+            Command::QueryFirmwareInfo => w.write_str("AT+GMR"),
+            Command::QueryIpAddress => w.write_str("AT+CIPSTA_CUR?"),
+            Command::QueryConnectionStatus => w.write_str("AT+CIPSTATUS"),
             Command::SetMode(mode)=> match mode {
-                WiFiMode::Station => String::from("AT+CWMODE_CUR=1"),
-                WiFiMode::SoftAccessPoint => String::from("AT+CWMODE_CUR=2"),
-                WiFiMode::SoftAccessPointAndStation => String::from("AT+CWMODE_CUR=3"),
-            }
-            Command::JoinAp { ssid, password } => {
-                let mut s = String::from("AT+CWJAP_CUR=\"");
-                s.push_str(ssid).unwrap();
-                s.push_str("\",\"").unwrap();
-                s.push_str(password).unwrap();
-                s.push_str("\"").unwrap();
-                s
-            }
-            Command::StartConnection(link_id, connection_type, socket_addr) => {
-                let mut s = String::from("AT+CIPSTART=");
-                write!(s, "{},", link_id).unwrap();
+                WiFiMode::Station => w.write_str("AT+CWMODE_CUR=1"),
+                WiFiMode::SoftAccessPoint => w.write_str("AT+CWMODE_CUR=2"),
+                WiFiMode::SoftAccessPointAndStation => w.write_str("AT+CWMODE_CUR=3"),
+            }
+            Command::JoinAp { ssid, password, bssid } => {
+                w.write_str("AT+CWJAP_CUR=")?;
+                write_quoted(w, ssid)?;
+                w.write_str(",")?;
+                write_quoted(w, password)?;
+                if let Some(bssid) = bssid {
+                    write!(
+                        w,
+                        ",\"{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\"",
+                        bssid[0], bssid[1], bssid[2], bssid[3], bssid[4], bssid[5]
+                    )?;
+                }
+                Ok(())
+            }
+            Command::StartConnection(link_id, connection_type, socket_addr, options) => {
+                write!(w, "AT+CIPSTART={},", link_id)?;
                 match connection_type {
                     ConnectionType::TCP => {
-                        write!(s, "\"TCP\"").unwrap();
+                        w.write_str("\"TCP\"")?;
                     }
                     ConnectionType::UDP => {
-                        write!(s, "\"UDP\"").unwrap();
+                        w.write_str("\"UDP\"")?;
                     }
                 }
-                write!(s, ",").unwrap();
+                w.write_str(",")?;
                 match socket_addr.ip() {
                     IpAddr::V4(ip) => {
                         let octets = ip.octets();
                         write!(
-                            s,
+                            w,
                             "\"{}.{}.{}.{}\",{}",
                             octets[0],
                             octets[1],
                             octets[2],
                             octets[3],
                             socket_addr.port()
-                        )
-                        .unwrap();
+                        )?;
                     }
-                    IpAddr::V6(_) => panic!("IPv6 not supported"),
+                    IpAddr::V6(_) => return Err(fmt::Error),
+                }
+                match options.keep_alive {
+                    Some(interval) => write!(w, ",{}", interval),
+                    None => Ok(()),
                 }
-                s as String<U128>
             }
             Command::CloseConnection(link_id) => {
-                let mut s = String::from("AT+CIPCLOSE=");
-                write!(s, "{}", link_id).unwrap();
-                s
+                write!(w, "AT+CIPCLOSE={}", link_id)
             }
             Command::Send { link_id, len } => {
-                let mut s = String::from("AT+CIPSEND=");
-                write!(s, "{},{}", link_id, len).unwrap();
-                s
+                write!(w, "AT+CIPSEND={},{}", link_id, len)
             }
             Command::Receive { link_id, len } => {
-                let mut s = String::from("AT+CIPRECVDATA=");
-                write!(s, "{},{}", link_id, len).unwrap();
-                s
+                write!(w, "AT+CIPRECVDATA={},{}", link_id, len)
+            }
+            Command::QueryReceiveLengths => {
+                w.write_str("AT+CIPRECVLEN?")
+            }
+            Command::SendTerminated { link_id, len } => {
+                write!(w, "AT+CIPSENDEX={},{}", link_id, len)
+            }
+            Command::SendBuffered { link_id, len } => {
+                write!(w, "AT+CIPSENDBUF={},{}", link_id, len)
+            }
+            Command::QueryBufferStatus(link_id) => {
+                write!(w, "AT+CIPBUFSTATUS={}", link_id)
+            }
+            Command::CheckSegment { link_id, segment_id } => {
+                write!(w, "AT+CIPCHECKSEQ={},{}", link_id, segment_id)
+            }
+            Command::ResetBuffer(link_id) => {
+                write!(w, "AT+CIPBUFRESET={}", link_id)
+            }
+            Command::SetServerTimeout(timeout) => {
+                write!(w, "AT+CIPSTO={}", timeout)
+            }
+            Command::QueryServerTimeout => {
+                w.write_str("AT+CIPSTO?")
             }
             Command::QueryDnsResolvers => {
-                String::from("AT+CIPDNS_CUR?")
+                w.write_str("AT+CIPDNS_CUR?")
             }
             Command::SetDnsResolvers(addr) => {
-                let mut s = String::from("AT+CIPDNS_CUR=1,");
-                write!(s, "\"{}\"", addr.resolver1).unwrap();
+                write!(w, "AT+CIPDNS_CUR=1,\"{}\"", addr.resolver1)?;
                 if let Some(resolver2) = addr.resolver2 {
-                    write!(s, ",\"{}\"", resolver2 ).unwrap()
+                    write!(w, ",\"{}\"", resolver2)?;
+                }
+                Ok(())
+            }
+            Command::GetHostByName { hostname } => {
+                w.write_str("AT+CIPDOMAIN=")?;
+                write_quoted(w, hostname)
+            }
+            Command::SetUart { config, persist } => {
+                if *persist {
+                    w.write_str("AT+UART_DEF=")?;
+                } else {
+                    w.write_str("AT+UART_CUR=")?;
+                }
+                write!(
+                    w,
+                    "{},{},{},{},{}",
+                    config.baud_rate,
+                    match config.data_bits {
+                        DataBits::Five => 5,
+                        DataBits::Six => 6,
+                        DataBits::Seven => 7,
+                        DataBits::Eight => 8,
+                    },
+                    match config.stop_bits {
+                        StopBits::One => 1,
+                        StopBits::OneAndHalf => 2,
+                        StopBits::Two => 3,
+                    },
+                    match config.parity {
+                        Parity::None => 0,
+                        Parity::Odd => 1,
+                        Parity::Even => 2,
+                    },
+                    match config.flow_control {
+                        FlowControl::None => 0,
+                        FlowControl::Rts => 1,
+                        FlowControl::Cts => 2,
+                        FlowControl::RtsCts => 3,
+                    },
+                )
+            }
+            Command::SetSntpConfig { enable: false, .. } => {
+                w.write_str("AT+CIPSNTPCFG=0")
+            }
+            Command::SetSntpConfig { enable: true, timezone, servers } => {
+                write!(w, "AT+CIPSNTPCFG=1,{}", timezone)?;
+                for server in servers.iter() {
+                    w.write_char(',')?;
+                    write_quoted(w, server)?;
                 }
-                s
+                Ok(())
+            }
+            Command::QuerySntpTime => {
+                w.write_str("AT+CIPSNTPTIME?")
             }
+            Command::Ping { host } => {
+                w.write_str("AT+PING=")?;
+                write_quoted(w, host)
+            }
+            Command::Raw(command) => w.write_str(command),
+        }
+    }
+}
+
+/// Write a quoted string argument, escaping the characters which the AT firmware
+/// treats specially (`"`, `,` and `\\`).
+fn write_quoted<W: Write>(w: &mut W, value: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in value.chars() {
+        if matches!(c, '"' | ',' | '\\') {
+            w.write_char('\\')?;
+        }
+        w.write_char(c)?;
+    }
+    w.write_char('"')
+}
+
+/// Placeholder for secrets in log output.
+struct Redacted;
+
+impl Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Split a raw command into the part which may be logged, and whether
+/// arguments were left out.
+fn redact_raw(command: &str) -> (&str, bool) {
+    match command.find('=') {
+        Some(index) => (&command[0..=index], true),
+        None => (command, false),
+    }
+}
+
+impl<'a> Debug for Command<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Test => f.write_str("Test"),
+            Command::DisableEcho => f.write_str("DisableEcho"),
+            Command::EnableMux => f.write_str("EnableMux"),
+            Command::SetPassiveRecvMode => f.write_str("SetPassiveRecvMode"),
+            Command::SetDataInfo(enable) => f.debug_tuple("SetDataInfo").field(enable).finish(),
+            Command::QueryFirmwareInfo => f.write_str("QueryFirmwareInfo"),
+            Command::SetMode(mode) => f.debug_tuple("SetMode").field(mode).finish(),
+            Command::JoinAp { ssid, bssid, .. } => f
+                .debug_struct("JoinAp")
+                .field("ssid", ssid)
+                .field("password", &Redacted)
+                .field("bssid", bssid)
+                .finish(),
+            Command::QueryIpAddress => f.write_str("QueryIpAddress"),
+            Command::QueryConnectionStatus => f.write_str("QueryConnectionStatus"),
+            Command::StartConnection(link_id, connection_type, socket_addr, options) => f
+                .debug_tuple("StartConnection")
+                .field(link_id)
+                .field(connection_type)
+                .field(socket_addr)
+                .field(options)
+                .finish(),
+            Command::CloseConnection(link_id) => f.debug_tuple("CloseConnection").field(link_id).finish(),
+            Command::Send { link_id, len } => f
+                .debug_struct("Send")
+                .field("link_id", link_id)
+                .field("len", len)
+                .finish(),
+            Command::Receive { link_id, len } => f
+                .debug_struct("Receive")
+                .field("link_id", link_id)
+                .field("len", len)
+                .finish(),
+            Command::QueryReceiveLengths => f.write_str("QueryReceiveLengths"),
+            Command::SendTerminated { link_id, len } => f
+                .debug_struct("SendTerminated")
+                .field("link_id", link_id)
+                .field("len", len)
+                .finish(),
+            Command::SendBuffered { link_id, len } => f
+                .debug_struct("SendBuffered")
+                .field("link_id", link_id)
+                .field("len", len)
+                .finish(),
+            Command::QueryBufferStatus(link_id) => f.debug_tuple("QueryBufferStatus").field(link_id).finish(),
+            Command::CheckSegment { link_id, segment_id } => f
+                .debug_struct("CheckSegment")
+                .field("link_id", link_id)
+                .field("segment_id", segment_id)
+                .finish(),
+            Command::ResetBuffer(link_id) => f.debug_tuple("ResetBuffer").field(link_id).finish(),
+            Command::SetServerTimeout(timeout) => f.debug_tuple("SetServerTimeout").field(timeout).finish(),
+            Command::QueryServerTimeout => f.write_str("QueryServerTimeout"),
+            Command::QueryDnsResolvers => f.write_str("QueryDnsResolvers"),
+            Command::SetDnsResolvers(resolvers) => f.debug_tuple("SetDnsResolvers").field(resolvers).finish(),
+            Command::GetHostByName { hostname } => f
+                .debug_struct("GetHostByName")
+                .field("hostname", hostname)
+                .finish(),
+            Command::SetUart { config, persist } => f
+                .debug_struct("SetUart")
+                .field("config", config)
+                .field("persist", persist)
+                .finish(),
+            Command::SetSntpConfig { enable, timezone, servers } => f
+                .debug_struct("SetSntpConfig")
+                .field("enable", enable)
+                .field("timezone", timezone)
+                .field("servers", servers)
+                .finish(),
+            Command::QuerySntpTime => f.write_str("QuerySntpTime"),
+            Command::Ping { host } => f.debug_struct("Ping").field("host", host).finish(),
+            Command::Raw(command) => match redact_raw(command) {
+                (command, true) => f.debug_tuple("Raw").field(&command).field(&Redacted).finish(),
+                (command, false) => f.debug_tuple("Raw").field(&command).finish(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for Command<'a> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            Command::Test => defmt::write!(f, "Test"),
+            Command::DisableEcho => defmt::write!(f, "DisableEcho"),
+            Command::EnableMux => defmt::write!(f, "EnableMux"),
+            Command::SetPassiveRecvMode => defmt::write!(f, "SetPassiveRecvMode"),
+            Command::SetDataInfo(enable) => defmt::write!(f, "SetDataInfo({})", enable),
+            Command::QueryFirmwareInfo => defmt::write!(f, "QueryFirmwareInfo"),
+            Command::SetMode(mode) => defmt::write!(f, "SetMode({})", mode),
+            Command::JoinAp { ssid, bssid, .. } => defmt::write!(
+                f,
+                "JoinAp {{ ssid: {=str}, password: <redacted>, bssid: {} }}",
+                ssid,
+                bssid
+            ),
+            Command::QueryIpAddress => defmt::write!(f, "QueryIpAddress"),
+            Command::QueryConnectionStatus => defmt::write!(f, "QueryConnectionStatus"),
+            Command::StartConnection(link_id, connection_type, socket_addr, options) => defmt::write!(
+                f,
+                "StartConnection({}, {}, {}, {})",
+                link_id,
+                connection_type,
+                defmt::Display2Format(socket_addr),
+                options
+            ),
+            Command::CloseConnection(link_id) => defmt::write!(f, "CloseConnection({})", link_id),
+            Command::Send { link_id, len } => {
+                defmt::write!(f, "Send {{ link_id: {}, len: {} }}", link_id, len)
+            }
+            Command::Receive { link_id, len } => {
+                defmt::write!(f, "Receive {{ link_id: {}, len: {} }}", link_id, len)
+            }
+            Command::QueryReceiveLengths => defmt::write!(f, "QueryReceiveLengths"),
+            Command::SendTerminated { link_id, len } => {
+                defmt::write!(f, "SendTerminated {{ link_id: {}, len: {} }}", link_id, len)
+            }
+            Command::SendBuffered { link_id, len } => {
+                defmt::write!(f, "SendBuffered {{ link_id: {}, len: {} }}", link_id, len)
+            }
+            Command::QueryBufferStatus(link_id) => defmt::write!(f, "QueryBufferStatus({})", link_id),
+            Command::CheckSegment { link_id, segment_id } => defmt::write!(
+                f,
+                "CheckSegment {{ link_id: {}, segment_id: {} }}",
+                link_id,
+                segment_id
+            ),
+            Command::ResetBuffer(link_id) => defmt::write!(f, "ResetBuffer({})", link_id),
+            Command::SetServerTimeout(timeout) => defmt::write!(f, "SetServerTimeout({})", timeout),
+            Command::QueryServerTimeout => defmt::write!(f, "QueryServerTimeout"),
+            Command::QueryDnsResolvers => defmt::write!(f, "QueryDnsResolvers"),
+            Command::SetDnsResolvers(resolvers) => defmt::write!(f, "SetDnsResolvers({})", resolvers),
             Command::GetHostByName { hostname } => {
-                let mut s = String::from("AT+CIPDOMAIN=");
-                write!(s, "\"{}\"", hostname).unwrap();
-                s
+                defmt::write!(f, "GetHostByName {{ hostname: {=str} }}", hostname)
             }
+            Command::SetUart { config, persist } => {
+                defmt::write!(f, "SetUart {{ config: {}, persist: {} }}", config, persist)
+            }
+            Command::SetSntpConfig { enable, timezone, servers } => defmt::write!(
+                f,
+                "SetSntpConfig {{ enable: {}, timezone: {}, servers: {=[?]} }}",
+                enable,
+                timezone,
+                servers
+            ),
+            Command::QuerySntpTime => defmt::write!(f, "QuerySntpTime"),
+            Command::Ping { host } => defmt::write!(f, "Ping {{ host: {=str} }}", host),
+            Command::Raw(command) => match redact_raw(command) {
+                (command, true) => defmt::write!(f, "Raw({=str}, <redacted>)", command),
+                (command, false) => defmt::write!(f, "Raw({=str})", command),
+            },
         }
     }
 }
@@ -135,6 +683,7 @@ impl<'a> Command<'a> {
 #[allow(clippy::large_enum_variant)]
 pub enum Response {
     None,
+    Ready,
     Ok,
     Error,
     FirmwareInfo(FirmwareInfo),
@@ -143,7 +692,8 @@ pub enum Response {
     SendOk,
     SendFail,
     DataAvailable { link_id: usize, len: usize },
-    DataReceived([u8; crate::BUFFER_LEN], usize),
+    /// Data read from a link, with the sender if `AT+CIPDINFO` is enabled.
+    DataReceived([u8; crate::BUFFER_LEN], usize, Option<SocketAddr>),
     WifiConnected,
     WifiConnectionFailure(WifiConnectionFailure),
     WifiDisconnect,
@@ -155,12 +705,45 @@ pub enum Response {
     IpAddress(IpAddr),
     DnsFail,
     UnlinkFail,
+    /// The link was already connected when starting a connection.
+    AlreadyConnected,
+    /// A station joined the soft-AP.
+    StationConnected([u8; 6]),
+    /// A station left the soft-AP.
+    StationDisconnected([u8; 6]),
+    /// A station of the soft-AP was assigned an IP address.
+    StationIp([u8; 6], Ipv4Addr),
+    /// Time of the SNTP client, in the configured timezone.
+    SntpTime(DateTime),
+    /// Round-trip time of a ping, in milliseconds.
+    PingReply(usize),
+    /// A ping was not answered in time.
+    PingTimeout,
+    ConnectionStatus(ConnectionStatus),
+    /// Octets pending on the board, indexed by link ID.
+    ReceiveLengths([usize; MAX_LINKS]),
+    /// Idle timeout, in seconds, of links accepted by the board's server.
+    ServerTimeout(u16),
+    /// A segment was queued into the send buffer, along with the latest segment
+    /// sent successfully.
+    SegmentQueued { segment_id: usize, sent_segment_id: usize },
+    /// A segment of the send buffer was sent.
+    SegmentSent { link_id: usize, segment_id: usize },
+    /// A segment of the send buffer failed to be sent.
+    SegmentFailed { link_id: usize, segment_id: usize },
+    BufferStatus(BufferStatus),
+    /// Whether a segment of the send buffer was sent, see `Command::CheckSegment`.
+    SegmentStatus { link_id: usize, segment_id: usize, sent: bool },
+    /// A line not recognized as any of the other responses, e.g. in response to
+    /// a raw command.
+    Line([u8; MAX_LINE_LEN], usize),
 }
 
 impl Debug for Response {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Response::None => f.write_str("None"),
+            Response::Ready => f.write_str("Ready"),
             Response::Ok => f.write_str("Ok"),
             Response::Error => f.write_str("Error"),
             Response::FirmwareInfo(v) => f.debug_tuple("FirmwareInfo").field(v).finish(),
@@ -173,8 +756,11 @@ impl Debug for Response {
                 .field("link_id", link_id)
                 .field("len", len)
                 .finish(),
-            //Response::DataReceived(d, l) => dump_data("DataReceived", d, *l, f),
-            Response::DataReceived(_, _) => f.write_str("DataReceived"),
+            Response::DataReceived(d, l, None) => dump_data("DataReceived", d, *l, f),
+            Response::DataReceived(d, l, Some(remote)) => {
+                dump_data("DataReceived", d, *l, f)?;
+                write!(f, " from {}", remote)
+            }
             Response::WifiConnected => f.write_str("WifiConnected"),
             Response::WifiConnectionFailure(v) => {
                 f.debug_tuple("WifiConnectionFailure").field(v).finish()
@@ -188,20 +774,362 @@ impl Debug for Response {
             Response::Resolvers(v) => f.debug_tuple( "Resolvers").field(v).finish(),
             Response::DnsFail => f.write_str("DNS Fail"),
             Response::UnlinkFail => f.write_str("UnlinkFail"),
+            Response::AlreadyConnected => f.write_str("AlreadyConnected"),
+            Response::StationConnected(mac) => f.debug_tuple("StationConnected").field(mac).finish(),
+            Response::StationDisconnected(mac) => f.debug_tuple("StationDisconnected").field(mac).finish(),
+            Response::StationIp(mac, ip) => f.debug_tuple("StationIp").field(mac).field(ip).finish(),
+            Response::SntpTime(v) => f.debug_tuple("SntpTime").field(v).finish(),
+            Response::PingReply(v) => f.debug_tuple("PingReply").field(v).finish(),
+            Response::PingTimeout => f.write_str("PingTimeout"),
+            Response::ConnectionStatus(v) => f.debug_tuple("ConnectionStatus").field(v).finish(),
+            Response::ReceiveLengths(v) => f.debug_tuple("ReceiveLengths").field(v).finish(),
+            Response::ServerTimeout(v) => f.debug_tuple("ServerTimeout").field(v).finish(),
+            Response::SegmentQueued { segment_id, sent_segment_id } => f
+                .debug_struct("SegmentQueued")
+                .field("segment_id", segment_id)
+                .field("sent_segment_id", sent_segment_id)
+                .finish(),
+            Response::SegmentSent { link_id, segment_id } => f
+                .debug_struct("SegmentSent")
+                .field("link_id", link_id)
+                .field("segment_id", segment_id)
+                .finish(),
+            Response::SegmentFailed { link_id, segment_id } => f
+                .debug_struct("SegmentFailed")
+                .field("link_id", link_id)
+                .field("segment_id", segment_id)
+                .finish(),
+            Response::BufferStatus(v) => f.debug_tuple("BufferStatus").field(v).finish(),
+            Response::SegmentStatus { link_id, segment_id, sent } => f
+                .debug_struct("SegmentStatus")
+                .field("link_id", link_id)
+                .field("segment_id", segment_id)
+                .field("sent", sent)
+                .finish(),
+            Response::Line(d, l) => dump_data("Line", d, *l, f),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Response {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            Response::None => defmt::write!(f, "None"),
+            Response::Ready => defmt::write!(f, "Ready"),
+            Response::Ok => defmt::write!(f, "Ok"),
+            Response::Error => defmt::write!(f, "Error"),
+            Response::FirmwareInfo(v) => defmt::write!(f, "FirmwareInfo({})", v),
+            Response::ReadyForData => defmt::write!(f, "ReadyForData"),
+            Response::ReceivedDataToSend(len) => defmt::write!(f, "ReceivedDataToSend({})", len),
+            Response::SendOk => defmt::write!(f, "SendOk"),
+            Response::SendFail => defmt::write!(f, "SendFail"),
+            Response::DataAvailable { link_id, len } => {
+                defmt::write!(f, "DataAvailable {{ link_id: {}, len: {} }}", link_id, len)
+            }
+            Response::DataReceived(d, l, None) => {
+                defmt::write!(f, "DataReceived({}; {=[u8]:a})", l, &d[0..*l])
+            }
+            Response::DataReceived(d, l, Some(remote)) => defmt::write!(
+                f,
+                "DataReceived({}; {=[u8]:a}) from {}",
+                l,
+                &d[0..*l],
+                defmt::Display2Format(remote)
+            ),
+            Response::WifiConnected => defmt::write!(f, "WifiConnected"),
+            Response::WifiConnectionFailure(v) => defmt::write!(f, "WifiConnectionFailure({})", v),
+            Response::WifiDisconnect => defmt::write!(f, "WifiDisconnect"),
+            Response::GotIp => defmt::write!(f, "GotIp"),
+            Response::IpAddresses(v) => defmt::write!(f, "IpAddresses({})", v),
+            Response::Connect(v) => defmt::write!(f, "Connect({})", v),
+            Response::Closed(v) => defmt::write!(f, "Closed({})", v),
+            Response::IpAddress(v) => defmt::write!(f, "IpAddress({})", defmt::Display2Format(v)),
+            Response::Resolvers(v) => defmt::write!(f, "Resolvers({})", v),
+            Response::DnsFail => defmt::write!(f, "DNS Fail"),
+            Response::UnlinkFail => defmt::write!(f, "UnlinkFail"),
+            Response::AlreadyConnected => defmt::write!(f, "AlreadyConnected"),
+            Response::StationConnected(mac) => defmt::write!(f, "StationConnected({})", mac),
+            Response::StationDisconnected(mac) => defmt::write!(f, "StationDisconnected({})", mac),
+            Response::StationIp(mac, ip) => {
+                defmt::write!(f, "StationIp({}, {})", mac, defmt::Display2Format(ip))
+            }
+            Response::SntpTime(v) => defmt::write!(f, "SntpTime({})", v),
+            Response::PingReply(v) => defmt::write!(f, "PingReply({})", v),
+            Response::PingTimeout => defmt::write!(f, "PingTimeout"),
+            Response::ConnectionStatus(v) => defmt::write!(f, "ConnectionStatus({})", v),
+            Response::ReceiveLengths(v) => defmt::write!(f, "ReceiveLengths({})", v),
+            Response::ServerTimeout(v) => defmt::write!(f, "ServerTimeout({})", v),
+            Response::SegmentQueued { segment_id, sent_segment_id } => defmt::write!(
+                f,
+                "SegmentQueued {{ segment_id: {}, sent_segment_id: {} }}",
+                segment_id,
+                sent_segment_id
+            ),
+            Response::SegmentSent { link_id, segment_id } => defmt::write!(
+                f,
+                "SegmentSent {{ link_id: {}, segment_id: {} }}",
+                link_id,
+                segment_id
+            ),
+            Response::SegmentFailed { link_id, segment_id } => defmt::write!(
+                f,
+                "SegmentFailed {{ link_id: {}, segment_id: {} }}",
+                link_id,
+                segment_id
+            ),
+            Response::BufferStatus(v) => defmt::write!(f, "BufferStatus({})", v),
+            Response::SegmentStatus { link_id, segment_id, sent } => defmt::write!(
+                f,
+                "SegmentStatus {{ link_id: {}, segment_id: {}, sent: {} }}",
+                link_id,
+                segment_id,
+                sent
+            ),
+            Response::Line(d, l) => defmt::write!(f, "Line({}; {=[u8]:a})", l, &d[0..*l]),
+        }
+    }
+}
+
+/// Verbosity of the trace of AT traffic, which is logged at `trace` level.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Trace {
+    /// Nothing is traced.
+    Off,
+    /// Commands and responses are traced, leaving out received data.
+    #[default]
+    Summary,
+    /// Commands and responses are traced, including received data.
+    Full,
+}
+
+/// A response for the trace, leaving out received data.
+pub(crate) struct Summary<'a>(pub(crate) &'a Response);
+
+impl<'a> Debug for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Response::DataReceived(_, len, _) => f.debug_tuple("DataReceived").field(len).finish(),
+            Response::Line(_, len) => f.debug_tuple("Line").field(len).finish(),
+            response => response.fmt(f),
         }
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for Summary<'a> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self.0 {
+            Response::DataReceived(_, len, _) => defmt::write!(f, "DataReceived({})", len),
+            Response::Line(_, len) => defmt::write!(f, "Line({})", len),
+            response => response.format(f),
+        }
+    }
+}
+
+/// Response to a raw command: the lines received up to the final response.
+pub struct RawResponse {
+    lines: Vec<([u8; MAX_LINE_LEN], usize), U8>,
+    response: Response,
+}
+
+impl RawResponse {
+    pub(crate) fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            response: Response::None,
+        }
+    }
+
+    /// Add a line, returning `false` if no more lines fit.
+    pub(crate) fn push_line(&mut self, line: [u8; MAX_LINE_LEN], len: usize) -> bool {
+        self.lines.push((line, len)).is_ok()
+    }
+
+    pub(crate) fn finish(&mut self, response: Response) {
+        self.response = response;
+    }
+
+    /// The lines preceding the final response, without line endings.
+    pub fn lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.lines.iter().map(|(line, len)| &line[0..*len])
+    }
+
+    /// The response terminating the command, usually `Ok` or `Error`.
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    /// Whether the board accepted the command.
+    pub fn is_ok(&self) -> bool {
+        matches!(self.response, Response::Ok)
+    }
+}
+
+impl Debug for RawResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RawResponse [")?;
+        for (line, len) in self.lines.iter() {
+            dump_data("Line", line, *len, f)?;
+            f.write_str(", ")?;
+        }
+        write!(f, "{:?}]", self.response)
+    }
+}
+
 /// IP addresses for the board, including its own address, netmask and gateway.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct IpAddresses {
+    #[cfg_attr(feature = "defmt", defmt(Display2Format))]
     pub ip: Ipv4Addr,
+    #[cfg_attr(feature = "defmt", defmt(Display2Format))]
     pub gateway: Ipv4Addr,
+    #[cfg_attr(feature = "defmt", defmt(Display2Format))]
     pub netmask: Ipv4Addr,
 }
 
+/// Calendar date and time of day, without timezone.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    /// Month of the year, from `1` to `12`.
+    pub month: u8,
+    /// Day of the month, from `1`.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00, assuming the date and time are UTC.
+    pub fn unix_timestamp(&self) -> i64 {
+        // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * 86_400 + self.hour as i64 * 3_600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// Date and time of seconds since 1970-01-01 00:00:00 UTC.
+    pub fn from_unix_timestamp(timestamp: i64) -> Self {
+        // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let days = timestamp.div_euclid(86_400) + 719_468;
+        let seconds = timestamp.rem_euclid(86_400);
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3_600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+/// State of the send buffer of a link, as reported by `AT+CIPBUFSTATUS`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BufferStatus {
+    /// ID of the next segment to be queued.
+    pub next_segment_id: usize,
+    /// ID of the latest segment handed to the TCP stack.
+    pub sent_segment_id: usize,
+    /// ID of the latest segment sent successfully.
+    pub acknowledged_segment_id: usize,
+    /// Free space of the buffer, in octets.
+    pub free: usize,
+    /// Number of segments queued.
+    pub queued: usize,
+}
+
+/// State of the station interface, as reported by `AT+CIPSTATUS`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StationStatus {
+    /// Joined an access-point and got an IP address.
+    GotIp,
+    /// Links are open.
+    Connected,
+    /// All links were closed.
+    Disconnected,
+    /// Not joined to any access-point.
+    NotConnected,
+    Unknown(u8),
+}
+
+impl From<u8> for StationStatus {
+    fn from(code: u8) -> Self {
+        match code {
+            2 => StationStatus::GotIp,
+            3 => StationStatus::Connected,
+            4 => StationStatus::Disconnected,
+            5 => StationStatus::NotConnected,
+            _ => StationStatus::Unknown(code),
+        }
+    }
+}
+
+/// An open link, as reported by `AT+CIPSTATUS`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStatus {
+    pub link_id: usize,
+    pub connection_type: ConnectionType,
+    #[cfg_attr(feature = "defmt", defmt(Display2Format))]
+    pub remote: SocketAddr,
+    pub local_port: u16,
+    /// Whether the board accepted the link as a server, rather than opening it.
+    pub server: bool,
+}
+
+/// Connections of the board, as reported by `AT+CIPSTATUS`.
+#[derive(Debug, Clone)]
+pub struct ConnectionStatus {
+    pub status: StationStatus,
+    pub links: Vec<LinkStatus, U5>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ConnectionStatus {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "ConnectionStatus {{ status: {}, links: {} }}",
+            self.status,
+            &self.links[..]
+        )
+    }
+}
+
+impl ConnectionStatus {
+    /// The details of a link, if it is open.
+    pub fn link(&self, link_id: usize) -> Option<&LinkStatus> {
+        self.links.iter().find(|link| link.link_id == link_id)
+    }
+}
+
 /// Version information for the ESP board.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FirmwareInfo {
     pub major: u8,
     pub minor: u8,
@@ -211,11 +1139,13 @@ pub struct FirmwareInfo {
 
 /// Reasons for Wifi access-point join failures.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WifiConnectionFailure {
     Timeout,
     WrongPassword,
     CannotFindTargetAp,
     ConnectionFailed,
+    InvalidArgument(ArgumentError),
 }
 
 
@@ -235,7 +1165,6 @@ impl From<u8> for WifiConnectionFailure {
 ///
 /// The output will contain the field name, the data as string (only 7bits) and the raw bytes
 /// in hex encoding.
-#[allow(dead_code)]
 fn dump_data(name: &str, data: &[u8], len: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     let data = &data[0..len];
 
@@ -265,7 +1194,7 @@ fn dump_data(name: &str, data: &[u8], len: usize, f: &mut fmt::Formatter<'_>) ->
 mod test {
     use super::*;
     use arrayvec::ArrayString;
-    use core::fmt::Write;
+    use drogue_network::addr::Ipv6Addr;
 
     #[test]
     fn test_debug_no_value() {
@@ -288,15 +1217,270 @@ mod test {
         let mut buf = ArrayString::<[u8; 256]>::new();
         let data = b"FOO\0BAR";
 
-        let mut array = [0u8; 128];
+        let mut array = [0u8; crate::BUFFER_LEN];
         for (&x, p) in data.iter().zip(array.iter_mut()) {
             *p = x;
         }
 
-        write!(&mut buf, "{:?}", Response::DataReceived(array, data.len())).expect("Can't write");
+        write!(&mut buf, "{:?}", Response::DataReceived(array, data.len(), None)).expect("Can't write");
         assert_eq!(
             &buf,
             "DataReceived(7; 'FOO\\0BAR'; [46, 4F, 4F, 0, 42, 41, 52])"
         );
     }
+
+    #[test]
+    fn test_debug_summary() {
+        let mut buf = ArrayString::<[u8; 32]>::new();
+
+        let array = [b'x'; crate::BUFFER_LEN];
+        write!(&mut buf, "{:?}", Summary(&Response::DataReceived(array, 7, None))).expect("Can't write");
+        assert_eq!(&buf, "DataReceived(7)");
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let mut buf = ArrayString::<[u8; 128]>::new();
+
+        let command = Command::JoinAp {
+            ssid: "ap",
+            password: "secret",
+            bssid: None,
+        };
+        write!(&mut buf, "{:?}", command).expect("Can't write");
+        assert_eq!(&buf, "JoinAp { ssid: \"ap\", password: <redacted>, bssid: None }");
+
+        buf.clear();
+        write!(&mut buf, "{:?}", Command::Raw("AT+CWSAP_CUR=\"ap\",\"secret\",5,3")).expect("Can't write");
+        assert_eq!(&buf, "Raw(\"AT+CWSAP_CUR=\", <redacted>)");
+
+        buf.clear();
+        write!(&mut buf, "{:?}", Command::Raw("AT+CWHOSTNAME?")).expect("Can't write");
+        assert_eq!(&buf, "Raw(\"AT+CWHOSTNAME?\")");
+    }
+
+    #[test]
+    fn test_join_escapes_arguments() {
+        let command = Command::JoinAp {
+            ssid: "my,\"ap\"",
+            password: "pass\\word",
+            bssid: None,
+        };
+        assert_eq!(
+            command.as_bytes().unwrap().as_str(),
+            "AT+CWJAP_CUR=\"my\\,\\\"ap\\\"\",\"pass\\\\word\""
+        );
+    }
+
+    #[test]
+    fn test_join_with_bssid() {
+        let command = Command::JoinAp {
+            ssid: "ap",
+            password: "password",
+            bssid: Some([0xca, 0xd7, 0x19, 0xd8, 0xa6, 0x44]),
+        };
+        assert_eq!(
+            command.as_bytes().unwrap().as_str(),
+            "AT+CWJAP_CUR=\"ap\",\"password\",\"ca:d7:19:d8:a6:44\""
+        );
+    }
+
+    #[test]
+    fn test_join_validates_lengths() {
+        let long = "0123456789012345678901234567890123456789012345678901234567890123456789";
+        let command = Command::JoinAp { ssid: "", password: "", bssid: None };
+        assert_eq!(command.validate(), Err(CommandError::InvalidArgument(ArgumentError::InvalidSsid)));
+        let command = Command::JoinAp { ssid: &long[0..33], password: "", bssid: None };
+        assert_eq!(command.validate(), Err(CommandError::InvalidArgument(ArgumentError::InvalidSsid)));
+        let command = Command::JoinAp { ssid: &long[0..32], password: &long[0..65], bssid: None };
+        assert_eq!(command.validate(), Err(CommandError::InvalidArgument(ArgumentError::InvalidPassword)));
+        let command = Command::JoinAp { ssid: &long[0..32], password: &long[0..64], bssid: None };
+        assert_eq!(command.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_set_uart() {
+        let mut config = UartConfig::new(921_600);
+        config.flow_control = FlowControl::RtsCts;
+        let command = Command::SetUart { config, persist: false };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+UART_CUR=921600,8,1,0,3");
+
+        let command = Command::SetUart { config: UartConfig::default(), persist: true };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+UART_DEF=115200,8,1,0,0");
+
+        let command = Command::SetUart { config: UartConfig::new(5_000_000), persist: false };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidBaudRate))
+        );
+    }
+
+    #[test]
+    fn test_start_connection_rejects_ipv6() {
+        let command = Command::StartConnection(
+            0,
+            ConnectionType::TCP,
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), 80),
+            ConnectionOptions::default(),
+        );
+        assert_eq!(command.as_bytes(), Err(CommandError::Unsupported));
+    }
+
+    #[test]
+    fn test_start_connection() {
+        let command = Command::StartConnection(
+            2,
+            ConnectionType::TCP,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 245)), 80),
+            ConnectionOptions::default(),
+        );
+        assert_eq!(
+            command.as_bytes().unwrap().as_str(),
+            "AT+CIPSTART=2,\"TCP\",\"192.168.1.245\",80"
+        );
+    }
+
+    #[test]
+    fn test_start_connection_with_options() {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 245)), 1883);
+        let keep_alive = ConnectionOptions {
+            keep_alive: Some(60),
+        };
+
+        let command = Command::StartConnection(2, ConnectionType::TCP, remote, keep_alive);
+        assert_eq!(
+            command.as_bytes().unwrap().as_str(),
+            "AT+CIPSTART=2,\"TCP\",\"192.168.1.245\",1883,60"
+        );
+        let command = Command::StartConnection(2, ConnectionType::UDP, remote, keep_alive);
+        assert_eq!(command.validate(), Err(CommandError::Unsupported));
+        let command = Command::StartConnection(
+            2,
+            ConnectionType::TCP,
+            remote,
+            ConnectionOptions {
+                keep_alive: Some(7201),
+            },
+        );
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidKeepAlive))
+        );
+    }
+
+    #[test]
+    fn test_set_server_timeout() {
+        let command = Command::SetServerTimeout(180);
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPSTO=180");
+        let command = Command::SetServerTimeout(7201);
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidServerTimeout))
+        );
+    }
+
+    #[test]
+    fn test_set_sntp_config() {
+        let command = Command::SetSntpConfig {
+            enable: true,
+            timezone: -5,
+            servers: &["0.pool.ntp.org", "time.google.com"],
+        };
+        assert_eq!(
+            command.as_bytes().unwrap().as_str(),
+            "AT+CIPSNTPCFG=1,-5,\"0.pool.ntp.org\",\"time.google.com\""
+        );
+
+        let command = Command::SetSntpConfig { enable: false, timezone: 0, servers: &[] };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPSNTPCFG=0");
+
+        let command = Command::SetSntpConfig { enable: true, timezone: 14, servers: &[] };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidTimezone))
+        );
+
+        let command = Command::SetSntpConfig { enable: true, timezone: 0, servers: &["a", "b", "c", "d"] };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidSntpServers))
+        );
+    }
+
+    #[test]
+    fn test_unix_timestamp() {
+        let epoch = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+        assert_eq!(epoch.unix_timestamp(), 0);
+        assert_eq!(DateTime::from_unix_timestamp(0), epoch);
+
+        let leap_day = DateTime { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 58 };
+        assert_eq!(leap_day.unix_timestamp(), 1_709_251_198);
+        assert_eq!(DateTime::from_unix_timestamp(1_709_251_198), leap_day);
+
+        let before_epoch = DateTime { year: 1969, month: 12, day: 31, hour: 23, minute: 0, second: 0 };
+        assert_eq!(DateTime::from_unix_timestamp(-3_600), before_epoch);
+    }
+
+    #[test]
+    fn test_ping() {
+        let command = Command::Ping { host: "192.168.1.1" };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+PING=\"192.168.1.1\"");
+
+        let command = Command::Ping { host: "" };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidHostname))
+        );
+    }
+
+    #[test]
+    fn test_query_connection_status() {
+        let command = Command::QueryConnectionStatus;
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPSTATUS");
+        assert_eq!(StationStatus::from(3), StationStatus::Connected);
+        assert_eq!(StationStatus::from(7), StationStatus::Unknown(7));
+    }
+
+    #[test]
+    fn test_query_receive_lengths() {
+        let command = Command::QueryReceiveLengths;
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPRECVLEN?");
+    }
+
+    #[test]
+    fn test_set_data_info() {
+        let command = Command::SetDataInfo(true);
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPDINFO=1");
+        let command = Command::SetDataInfo(false);
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPDINFO=0");
+    }
+
+    #[test]
+    fn test_send_buffer() {
+        let command = Command::SendBuffered { link_id: 1, len: 512 };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPSENDBUF=1,512");
+        let command = Command::QueryBufferStatus(1);
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPBUFSTATUS=1");
+        let command = Command::CheckSegment { link_id: 1, segment_id: 3 };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPCHECKSEQ=1,3");
+        let command = Command::ResetBuffer(1);
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPBUFRESET=1");
+
+        let command = Command::SendBuffered { link_id: 1, len: MAX_SEGMENT_LEN + 1 };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidSegmentLength))
+        );
+    }
+
+    #[test]
+    fn test_send_terminated() {
+        let command = Command::SendTerminated { link_id: 0, len: MAX_SEGMENT_LEN };
+        assert_eq!(command.as_bytes().unwrap().as_str(), "AT+CIPSENDEX=0,2048");
+        let command = Command::SendTerminated { link_id: 0, len: 0 };
+        assert_eq!(
+            command.validate(),
+            Err(CommandError::InvalidArgument(ArgumentError::InvalidSegmentLength))
+        );
+    }
 }
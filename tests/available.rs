@@ -0,0 +1,26 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_network::tcp::{Mode, TcpStack};
+
+#[test]
+fn recovers_lost_data_notification() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPRECVLEN?", "+CIPRECVLEN:5,0,0,0,0\r\n\r\nOK\r\n")
+        .respond("AT+CIPRECVDATA", "+CIPRECVDATA,5:hello\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        // the +IPD notification never made it to the adapter
+        let mut buffer = [0; 16];
+        assert!(matches!(network.read(&mut socket, &mut buffer), Err(nb::Error::WouldBlock)));
+
+        assert_eq!(network.available(&socket).unwrap(), 5);
+        assert_eq!(network.read(&mut socket, &mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[0..5], b"hello");
+    });
+}
@@ -0,0 +1,362 @@
+//! A simulated ESP8266, answering the commands written by the driver with
+//! scripted responses.
+
+#![allow(dead_code)]
+
+use drogue_esp8266::adapter::Adapter;
+use drogue_esp8266::ingress::IngressBuffer;
+use drogue_esp8266::initialize;
+use drogue_esp8266::protocol::Response;
+use drogue_network::addr::{HostAddr, HostSocketAddr};
+use heapless::consts::{U16, U2};
+use heapless::spsc::Queue;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct State {
+    /// Octets to be read by the driver.
+    pub output: VecDeque<u8>,
+    /// Octets written by the driver, not yet forming a complete command.
+    pub input: Vec<u8>,
+    /// Commands received, without line endings.
+    pub commands: Vec<String>,
+    /// Responses for commands, matched by prefix.
+    pub responses: Vec<(String, String)>,
+    /// Responses used only once, in order, before those in `responses`.
+    pub responses_once: Vec<(String, String)>,
+    /// Output after a reset, through the pin or `AT+RST`.
+    pub boot: String,
+    /// Whether the reset pin is connected.
+    pub reset_pin_connected: bool,
+    /// Whether the line carries garbage once all output was read, e.g. at the
+    /// wrong baud rate.
+    pub noisy: bool,
+    /// Data being received after a send command was answered with `> `.
+    pub transfer: Option<Transfer>,
+    /// Data received through send commands.
+    pub data: Vec<Vec<u8>>,
+    /// Octets to be written before writes fail, e.g. at a broken wire.
+    pub writable: Option<usize>,
+}
+
+/// Data phase of `AT+CIPSEND`, `AT+CIPSENDBUF` and `AT+CIPSENDEX`.
+pub struct Transfer {
+    len: usize,
+    /// Ends early on `\0`, see `AT+CIPSENDEX`.
+    terminated: bool,
+    /// `SEND OK` is left to the test, see `AT+CIPSENDBUF`.
+    buffered: bool,
+    data: Vec<u8>,
+    escaped: bool,
+}
+
+impl Transfer {
+    fn for_command(command: &str) -> Option<Self> {
+        let (name, args) = command.split_once('=')?;
+        let len = args.split(',').nth(1)?.parse().ok()?;
+        let (terminated, buffered) = match name {
+            "AT+CIPSEND" => (false, false),
+            "AT+CIPSENDEX" => (true, false),
+            "AT+CIPSENDBUF" => (false, true),
+            _ => return None,
+        };
+        Some(Self {
+            len,
+            terminated,
+            buffered,
+            data: Vec::new(),
+            escaped: false,
+        })
+    }
+
+    /// Receive an octet, returning whether the transfer is complete.
+    fn receive(&mut self, octet: u8) -> bool {
+        if self.terminated && self.escaped {
+            self.escaped = false;
+            match octet {
+                b'0' => return true,
+                octet => self.data.push(octet),
+            }
+        } else if self.terminated && octet == b'\\' {
+            self.escaped = true;
+        } else {
+            self.data.push(octet);
+        }
+        self.data.len() >= self.len
+    }
+
+    fn response(&self) -> String {
+        if self.buffered {
+            format!("Recv {} bytes\r\n", self.data.len())
+        } else {
+            format!("Recv {} bytes\r\n\r\nSEND OK\r\n", self.data.len())
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Module {
+    pub state: Arc<Mutex<State>>,
+}
+
+impl Module {
+    pub fn new() -> Self {
+        let module = Self::default();
+        {
+            let mut state = module.state.lock().unwrap();
+            state.boot = "\u{1}\u{80}garbage\r\nboot message\r\n\r\nready\r\n".into();
+            state.reset_pin_connected = true;
+        }
+        module
+            .respond("AT+RST", "\r\nOK\r\n")
+            .respond("AT", "\r\nOK\r\n")
+    }
+
+    /// Answer commands starting with `prefix`. Later entries take precedence.
+    pub fn respond(self, prefix: &str, response: &str) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(0, (prefix.into(), response.into()));
+        self
+    }
+
+    /// Answer the next command starting with `prefix` only. Entries for the
+    /// same prefix are used in the order they were added.
+    pub fn respond_once(self, prefix: &str, response: &str) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .responses_once
+            .push((prefix.into(), response.into()));
+        self
+    }
+
+    pub fn send(&self, output: &str) {
+        self.state.lock().unwrap().output.extend(output.bytes());
+    }
+
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    /// Data received through send commands.
+    pub fn data(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().data.clone()
+    }
+
+    fn boot(&self) {
+        let mut state = self.state.lock().unwrap();
+        let boot = state.boot.clone();
+        state.output.extend(boot.bytes());
+    }
+
+    pub fn tx(&self) -> Tx {
+        Tx { module: self.clone() }
+    }
+
+    pub fn rx(&self) -> Rx {
+        Rx { module: self.clone() }
+    }
+
+    pub fn reset_pin(&self) -> ResetPin {
+        ResetPin { module: self.clone(), low: false }
+    }
+}
+
+pub struct Tx {
+    module: Module,
+}
+
+/// Write to a broken wire.
+#[derive(Debug)]
+pub struct TxError;
+
+impl embedded_hal::serial::Write<u8> for Tx {
+    type Error = TxError;
+
+    fn write(&mut self, octet: u8) -> nb::Result<(), Self::Error> {
+        let mut state = self.module.state.lock().unwrap();
+        match state.writable {
+            Some(0) => return Err(nb::Error::Other(TxError)),
+            Some(ref mut n) => *n -= 1,
+            None => {}
+        }
+        if let Some(transfer) = state.transfer.as_mut() {
+            if transfer.receive(octet) {
+                let transfer = state.transfer.take().unwrap();
+                state.output.extend(transfer.response().bytes());
+                state.data.push(transfer.data);
+            }
+            return Ok(());
+        }
+        state.input.push(octet);
+        if !state.input.ends_with(b"\r\n") {
+            return Ok(());
+        }
+        let command = String::from_utf8_lossy(&state.input[..state.input.len() - 2]).into_owned();
+        state.input.clear();
+        state.commands.push(command.clone());
+        let once = state
+            .responses_once
+            .iter()
+            .position(|(prefix, _)| command.starts_with(prefix.as_str()));
+        let response = match once {
+            Some(index) => Some(state.responses_once.remove(index).1),
+            None => state
+                .responses
+                .iter()
+                .find(|(prefix, _)| command.starts_with(prefix.as_str()))
+                .map(|(_, response)| response.clone()),
+        };
+        if let Some(response) = response {
+            if response.ends_with("> ") {
+                state.transfer = Transfer::for_command(&command);
+            }
+            state.output.extend(response.bytes());
+        }
+        drop(state);
+        if command == "AT+RST" {
+            self.module.boot();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl embedded_hal::blocking::serial::write::Default<u8> for Tx {}
+
+pub struct Rx {
+    module: Module,
+}
+
+impl embedded_hal::serial::Read<u8> for Rx {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut state = self.module.state.lock().unwrap();
+        match state.output.pop_front() {
+            Some(octet) => Ok(octet),
+            None if state.noisy => Ok(0xff),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+pub struct ResetPin {
+    module: Module,
+    low: bool,
+}
+
+impl embedded_hal::digital::v2::OutputPin for ResetPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.low = true;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let connected = self.module.state.lock().unwrap().reset_pin_connected;
+        if self.low && connected {
+            self.module.boot();
+        }
+        self.low = false;
+        Ok(())
+    }
+}
+
+pub struct EnablePin;
+
+impl embedded_hal::digital::v2::OutputPin for EnablePin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Delay which only records the delays requested.
+#[derive(Default)]
+pub struct Delay {
+    pub delays: Vec<u32>,
+}
+
+impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        self.delays.push(us);
+    }
+}
+
+/// Delay which sleeps, leaving time for the ingress to be serviced from
+/// another thread while the adapter waits for a response.
+pub struct SleepDelay;
+
+impl embedded_hal::blocking::delay::DelayUs<u32> for SleepDelay {
+    fn delay_us(&mut self, us: u32) {
+        thread::sleep(Duration::from_micros(us as u64));
+    }
+}
+
+/// Address of the remote end of connections.
+pub fn remote() -> HostSocketAddr {
+    HostSocketAddr::new(HostAddr::ipv4([192, 168, 1, 1]), 8080)
+}
+
+/// Run `f` against an initialized adapter, while the ingress is serviced
+/// from another thread.
+pub fn run<F>(module: &Module, f: F)
+where
+    F: FnOnce(Adapter<'_, Tx>),
+{
+    let mut ingress_buffer = IngressBuffer::new();
+    let mut response_queue: Queue<Response, U2> = Queue::new();
+    let mut notification_queue: Queue<Response, U16> = Queue::new();
+    let mut delay = SleepDelay;
+    let (adapter, mut ingress) = initialize(
+        module.tx(),
+        module.rx(),
+        &mut EnablePin,
+        &mut module.reset_pin(),
+        &mut delay,
+        &mut ingress_buffer,
+        &mut response_queue,
+        &mut notification_queue,
+    )
+    .unwrap();
+
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while !stop.load(Ordering::Relaxed) {
+                let _ = ingress.isr();
+                ingress.digest();
+                thread::yield_now();
+            }
+        });
+        let _stop = Stop(&stop);
+        f(adapter);
+    });
+}
+
+/// Stops the ingress thread, even when the test panics.
+struct Stop<'a>(&'a AtomicBool);
+
+impl Drop for Stop<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{run, Module};
+use drogue_network::addr::{HostAddr, HostSocketAddr, IpAddr, Ipv4Addr, SocketAddr};
+use drogue_network::tcp::{Mode, TcpStack};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn reports_sender_of_received_data() {
+    let module = Module::new()
+        .respond("AT+CIPDINFO=1", "\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPRECVDATA", "+CIPRECVDATA,5,\"192.168.1.1\",8080:hello\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_data_info(true).unwrap();
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let remote = HostSocketAddr::new(HostAddr::ipv4([192, 168, 1, 1]), 8080);
+        let mut socket = network.connect(socket, remote).unwrap();
+
+        module.send("+IPD,0,5,\"192.168.1.1\",8080\r\n");
+        thread::sleep(Duration::from_millis(50));
+
+        let mut buffer = [0; 16];
+        let (len, sender) = network.read_from(&mut socket, &mut buffer).unwrap();
+        assert_eq!(&buffer[0..len], b"hello");
+        assert_eq!(
+            sender,
+            Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 8080))
+        );
+    });
+}
@@ -0,0 +1,78 @@
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use drogue_esp8266::ingress::{Ingress, IngressBuffer};
+use drogue_esp8266::protocol::Response;
+use embedded_hal::digital::v2::OutputPin;
+use heapless::consts::{U16, U2};
+use heapless::spsc::Queue;
+
+/// Serial receiver without any octets, data is ingested as slices instead.
+struct NoRx;
+
+impl embedded_hal::serial::Read<u8> for NoRx {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+/// RTS pin recording its level.
+#[derive(Default)]
+struct Rts {
+    high: Rc<Cell<bool>>,
+}
+
+impl OutputPin for Rts {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high.set(true);
+        Ok(())
+    }
+}
+
+#[test]
+fn pauses_board_without_digest() {
+    let mut response_queue: Queue<Response, U2> = Queue::new();
+    let mut notification_queue: Queue<Response, U16> = Queue::new();
+    let (response_producer, _) = response_queue.split();
+    let (notification_producer, _) = notification_queue.split();
+    let mut ingress_buffer = IngressBuffer::new();
+    let mut rts = Rts::default();
+    let level = rts.high.clone();
+
+    let ingress = Ingress::new(NoRx, &mut ingress_buffer, response_producer, notification_producer)
+        .with_rts(&mut rts);
+    let (mut isr, mut digest) = ingress.split();
+    let high = || level.get();
+    assert!(!high());
+
+    // the digest loop falls behind, the ISR alone has to pause the board
+    let frame = b"WIFI CONNECTED\r\n";
+    for _ in 0..150 {
+        isr.ingest(frame).unwrap();
+    }
+    assert!(!high());
+    for _ in 0..50 {
+        isr.ingest(frame).unwrap();
+    }
+    assert!(high());
+
+    // and the digest loop resumes it once caught up
+    for _ in 0..120 {
+        digest.digest();
+    }
+    assert!(high());
+    for _ in 0..80 {
+        digest.digest();
+    }
+    assert!(!high());
+}
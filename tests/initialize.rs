@@ -0,0 +1,94 @@
+mod common;
+
+use common::{Delay, EnablePin, Module};
+use drogue_esp8266::adapter::AdapterError;
+use drogue_esp8266::ingress::IngressBuffer;
+use drogue_esp8266::initialize;
+use drogue_esp8266::protocol::Response;
+use heapless::consts::{U16, U2};
+use heapless::spsc::Queue;
+
+fn run(module: &Module) -> Result<(), AdapterError> {
+    run_with(module, &mut Delay::default())
+}
+
+fn run_with(module: &Module, delay: &mut Delay) -> Result<(), AdapterError> {
+    let mut ingress_buffer = IngressBuffer::new();
+    let mut response_queue: Queue<Response, U2> = Queue::new();
+    let mut notification_queue: Queue<Response, U16> = Queue::new();
+    initialize(
+        module.tx(),
+        module.rx(),
+        &mut EnablePin,
+        &mut module.reset_pin(),
+        delay,
+        &mut ingress_buffer,
+        &mut response_queue,
+        &mut notification_queue,
+    )
+    .map(|_| ())
+}
+
+#[test]
+fn discards_boot_messages() {
+    let module = Module::new();
+    run(&module).unwrap();
+    assert_eq!(
+        module.commands(),
+        ["AT", "ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1"]
+    );
+}
+
+#[test]
+fn falls_back_to_software_reset() {
+    let module = Module::new();
+    module.state.lock().unwrap().reset_pin_connected = false;
+    run(&module).unwrap();
+    assert_eq!(
+        module.commands(),
+        ["AT+RST", "AT", "ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1"]
+    );
+}
+
+#[test]
+fn retries_failed_configuration() {
+    let module = Module::new().respond("AT+CIPMUX", "\r\nERROR\r\n");
+    assert!(matches!(run(&module), Err(AdapterError::UnableToInitialize)));
+    assert_eq!(
+        module
+            .commands()
+            .iter()
+            .filter(|command| command.as_str() == "AT+CIPMUX=1")
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn backs_off_between_attempts_only() {
+    let module = Module::new().respond("AT+CIPMUX", "\r\nERROR\r\n");
+    let mut delay = Delay::default();
+    assert!(matches!(run_with(&module, &mut delay), Err(AdapterError::UnableToInitialize)));
+    // polling and the reset pulse are far shorter than the backoff
+    let backoffs: Vec<_> = delay.delays.into_iter().filter(|us| *us >= 100_000).collect();
+    assert_eq!(backoffs, [100_000, 200_000]);
+}
+
+#[test]
+fn gives_up_on_silent_module() {
+    let module = Module::new();
+    module.state.lock().unwrap().boot.clear();
+    assert!(matches!(run(&module), Err(AdapterError::UnableToInitialize)));
+}
+
+#[test]
+fn gives_up_on_noisy_module() {
+    // e.g. at the wrong baud rate, where `ready` never makes it through
+    let module = Module::new();
+    {
+        let mut state = module.state.lock().unwrap();
+        state.boot.clear();
+        state.noisy = true;
+    }
+    assert!(matches!(run(&module), Err(AdapterError::UnableToInitialize)));
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+use std::thread;
+use std::time::Duration;
+
+fn count(module: &Module, prefix: &str) -> usize {
+    module.commands().iter().filter(|c| c.starts_with(prefix)).count()
+}
+
+#[test]
+fn failed_connect_releases_link() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond_once("AT+CIPSTART=0", "0,CLOSED\r\n\r\nERROR\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        assert!(network.connect(socket, remote()).is_err());
+
+        // the link is free again, and the `CLOSED` of the failed attempt
+        // does not affect the next connection on it
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+        assert_eq!(count(&module, "AT+CIPSTART=0"), 2);
+    });
+}
+
+#[test]
+fn failed_connects_do_not_exhaust_links() {
+    let module = Module::new().respond("AT+CIPSTART", "\r\nERROR\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        for _ in 0..10 {
+            let socket = network.open(Mode::NonBlocking).unwrap();
+            assert!(network.connect(socket, remote()).is_err());
+        }
+        assert_eq!(count(&module, "AT+CIPSTART=0"), 10);
+    });
+}
+
+#[test]
+fn reads_from_unconnected_socket_fail() {
+    let module = Module::new();
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let mut socket = network.open(Mode::NonBlocking).unwrap();
+        let mut buffer = [0; 16];
+        assert!(matches!(
+            network.read(&mut socket, &mut buffer),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+        assert!(matches!(
+            network.write(&mut socket, b"hello"),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+        network.close(socket).unwrap();
+        assert_eq!(count(&module, "AT+CIPRECVDATA"), 0);
+        assert_eq!(count(&module, "AT+CIPSEND"), 0);
+        assert_eq!(count(&module, "AT+CIPCLOSE"), 0);
+    });
+}
+
+#[test]
+fn closes_stale_link_when_already_connected() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond_once("AT+CIPSTART=0", "ALREADY CONNECTED\r\n\r\nERROR\r\n")
+        .respond("AT+CIPCLOSE", "0,CLOSED\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+
+        let commands: Vec<_> = module
+            .commands()
+            .into_iter()
+            .filter(|c| c.starts_with("AT+CIPSTART") || c.starts_with("AT+CIPCLOSE"))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "AT+CIPSTART=0,\"TCP\",\"192.168.1.1\",8080",
+                "AT+CIPCLOSE=0",
+                "AT+CIPSTART=0,\"TCP\",\"192.168.1.1\",8080",
+            ]
+        );
+    });
+}
+
+#[test]
+fn remote_close_keeps_unread_data() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPRECVDATA", "+CIPRECVDATA,5:hello\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        module.send("\r\n+IPD,0,5\r\n0,CLOSED\r\n");
+        thread::sleep(Duration::from_millis(50));
+
+        // half-closed, until the pending data is read
+        assert!(network.is_connected(&socket).unwrap());
+        let mut buffer = [0; 16];
+        assert_eq!(network.read(&mut socket, &mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[0..5], b"hello");
+
+        assert!(!network.is_connected(&socket).unwrap());
+        assert!(matches!(
+            network.read(&mut socket, &mut buffer),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+        assert!(matches!(
+            network.write(&mut socket, b"hello"),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+
+        // the module already released the link
+        network.close(socket).unwrap();
+        assert_eq!(count(&module, "AT+CIPSEND"), 0);
+        assert_eq!(count(&module, "AT+CIPCLOSE"), 0);
+    });
+}
+
+#[test]
+fn local_close_discards_pending_data() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPCLOSE", "0,CLOSED\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+
+        module.send("\r\n+IPD,0,5\r\n");
+        thread::sleep(Duration::from_millis(50));
+        network.close(socket).unwrap();
+
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+        let mut buffer = [0; 16];
+        assert!(matches!(
+            network.read(&mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert_eq!(count(&module, "AT+CIPRECVDATA"), 0);
+    });
+}
+
+#[test]
+fn failed_close_releases_link() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPCLOSE", "\r\nERROR\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+        assert!(network.close(socket).is_err());
+
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        network.connect(socket, remote()).unwrap();
+        assert_eq!(count(&module, "AT+CIPSTART=0"), 2);
+    });
+}
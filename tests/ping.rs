@@ -0,0 +1,45 @@
+mod common;
+
+use common::{run, Module};
+use drogue_esp8266::adapter::AdapterError;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn reports_round_trip_time() {
+    let module = Module::new()
+        .respond("AT+PING=\"192.168.1.1\"", "+5\r\n\r\nOK\r\n")
+        .respond("AT+PING=\"drogue.io\"", "+timeout\r\n\r\nERROR\r\n")
+        .respond("AT+PING=\"nowhere\"", "ERROR\r\n");
+
+    run(&module, |mut adapter| {
+        assert_eq!(adapter.ping("192.168.1.1").unwrap(), 5);
+        assert!(matches!(adapter.ping("drogue.io"), Err(AdapterError::Timeout)));
+        assert!(matches!(adapter.ping("nowhere"), Err(AdapterError::HostUnreachable)));
+    });
+}
+
+#[test]
+fn times_out_without_response() {
+    let module = Module::new().respond("AT+PING", "");
+
+    run(&module, |mut adapter| {
+        adapter.set_timeout(100_000);
+        assert!(matches!(adapter.ping("192.168.1.1"), Err(AdapterError::Timeout)));
+    });
+}
+
+#[test]
+fn ignores_late_reply_of_timed_out_ping() {
+    let module = Module::new().respond("AT+PING", "+7\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        let _ = module.clone().respond_once("AT+PING", "");
+        adapter.set_timeout(100_000);
+        assert!(matches!(adapter.ping("192.168.1.1"), Err(AdapterError::Timeout)));
+
+        module.send("+1500\r\n\r\nOK\r\n");
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(adapter.ping("192.168.1.1").unwrap(), 7);
+    });
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{run, Module};
+use drogue_esp8266::adapter::AdapterError;
+use drogue_esp8266::protocol::{ArgumentError, CommandError, Response, WiFiMode};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn collects_lines_up_to_ok() {
+    let module = Module::new().respond(
+        "AT+CWHOSTNAME?",
+        "+CWHOSTNAME:ESP_1A2B3C\r\n\r\nOK\r\n",
+    );
+
+    run(&module, |mut adapter| {
+        let response = adapter.send_raw("AT+CWHOSTNAME?").unwrap();
+        assert!(response.is_ok());
+        assert_eq!(
+            response.lines().collect::<Vec<_>>(),
+            [b"+CWHOSTNAME:ESP_1A2B3C".as_ref()]
+        );
+        assert_eq!(module.commands().last().unwrap(), "AT+CWHOSTNAME?");
+    });
+}
+
+#[test]
+fn reports_error() {
+    let module = Module::new().respond("AT+SLEEP", "\r\nERROR\r\n");
+
+    run(&module, |mut adapter| {
+        let response = adapter.send_raw("AT+SLEEP=9").unwrap();
+        assert!(!response.is_ok());
+        assert!(matches!(response.response(), Response::Error));
+        assert_eq!(response.lines().count(), 0);
+    });
+}
+
+#[test]
+fn rejects_line_endings() {
+    let module = Module::new();
+
+    run(&module, |mut adapter| {
+        assert!(matches!(
+            adapter.send_raw("AT+RST\r\nAT"),
+            Err(AdapterError::InvalidCommand(CommandError::InvalidArgument(
+                ArgumentError::InvalidRawCommand
+            )))
+        ));
+        assert!(matches!(
+            adapter.send_raw("RST"),
+            Err(AdapterError::InvalidCommand(CommandError::InvalidArgument(
+                ArgumentError::InvalidRawCommand
+            )))
+        ));
+        assert_eq!(module.commands().len(), 4);
+    });
+}
+
+#[test]
+fn stray_lines_do_not_disturb_commands() {
+    let module = Module::new().respond("AT+CWMODE_CUR", "busy p...\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_mode(WiFiMode::Station).unwrap();
+    });
+}
+
+#[test]
+fn drops_unsolicited_lines() {
+    let module = Module::new().respond(
+        "AT+CWHOSTNAME?",
+        "+CWHOSTNAME:ESP_1A2B3C\r\n\r\nOK\r\n",
+    );
+
+    run(&module, |mut adapter| {
+        // more lines than the response queue holds, while no command is pending
+        module.send("busy p...\r\nbusy p...\r\nbusy p...\r\nwdt reset\r\n");
+        thread::sleep(Duration::from_millis(50));
+
+        let response = adapter.send_raw("AT+CWHOSTNAME?").unwrap();
+        assert_eq!(
+            response.lines().collect::<Vec<_>>(),
+            [b"+CWHOSTNAME:ESP_1A2B3C".as_ref()]
+        );
+    });
+}
+
+#[test]
+fn times_out_without_response() {
+    let module = Module::new().respond("AT+CWHOSTNAMEX?", "");
+
+    run(&module, |mut adapter| {
+        adapter.set_timeout(100_000);
+        assert!(matches!(
+            adapter.send_raw("AT+CWHOSTNAMEX?"),
+            Err(AdapterError::Timeout)
+        ));
+    });
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_esp8266::protocol::{UartConfig, WiFiMode};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Let the module crash and reboot, and give the ingress time to pick it up.
+fn crash(module: &Module) {
+    module.state.lock().unwrap().commands.clear();
+    module.send("\u{1}\u{80}garbage\r\nready\r\n");
+    thread::sleep(Duration::from_millis(50));
+}
+
+#[test]
+fn restores_configuration_after_reset() {
+    let module = Module::new()
+        .respond("AT+CWMODE_CUR", "\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_mode(WiFiMode::Station).unwrap();
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+
+        crash(&module);
+
+        let mut buffer = [0; 16];
+        assert!(matches!(
+            network.read(&mut socket, &mut buffer),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+        assert!(!network.is_connected(&socket).unwrap());
+        assert_eq!(
+            module.commands(),
+            ["ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1", "AT+CWMODE_CUR=1"]
+        );
+
+        // the module dropped the link already, closing must not talk to it
+        network.close(socket).unwrap();
+        assert_eq!(module.commands().len(), 4);
+
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+    });
+}
+
+#[test]
+fn rejoins_access_point_after_reset() {
+    let module = Module::new()
+        .respond("AT+CWMODE_CUR", "\r\nOK\r\n")
+        .respond("AT+CWJAP_CUR", "WIFI CONNECTED\r\nWIFI GOT IP\r\n\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_mode(WiFiMode::Station).unwrap();
+        adapter.set_rejoin_on_reset(true);
+        adapter.join("drogue", "rodney").unwrap();
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        crash(&module);
+
+        let mut buffer = [0; 16];
+        assert!(network.read(&mut socket, &mut buffer).is_err());
+        assert_eq!(
+            module.commands(),
+            [
+                "ATE0",
+                "AT+CIPMUX=1",
+                "AT+CIPRECVMODE=1",
+                "AT+CWMODE_CUR=1",
+                "AT+CWJAP_CUR=\"drogue\",\"rodney\"",
+            ]
+        );
+    });
+}
+
+#[test]
+fn forgets_access_point_without_rejoin() {
+    let module = Module::new()
+        .respond("AT+CWJAP_CUR", "WIFI CONNECTED\r\nWIFI GOT IP\r\n\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        // joined before enabling it, and discarded when disabling it
+        adapter.join("drogue", "rodney").unwrap();
+        adapter.set_rejoin_on_reset(true);
+        adapter.set_rejoin_on_reset(false);
+        adapter.join("drogue", "rodney").unwrap();
+        adapter.set_rejoin_on_reset(true);
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        crash(&module);
+
+        let mut buffer = [0; 16];
+        assert!(network.read(&mut socket, &mut buffer).is_err());
+        assert_eq!(module.commands(), ["ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1"]);
+    });
+}
+
+#[test]
+fn restores_uart_configuration_after_reset() {
+    let module = Module::new()
+        .respond("AT+UART_CUR", "\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        let baud_rates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = baud_rates.clone();
+        let reconfigure = Box::leak(Box::new(move |config: &UartConfig| {
+            recorded.lock().unwrap().push(config.baud_rate);
+            Ok(())
+        }));
+        adapter.set_uart_recovery(reconfigure);
+        adapter.set_uart(UartConfig::new(921_600), false, |_| Ok::<(), ()>(())).unwrap();
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        crash(&module);
+
+        // back to the board's default, before switching again
+        let mut buffer = [0; 16];
+        assert!(network.read(&mut socket, &mut buffer).is_err());
+        assert_eq!(*baud_rates.lock().unwrap(), [115_200, 921_600]);
+        assert_eq!(
+            module.commands(),
+            ["AT+UART_CUR=921600,8,1,0,0", "AT", "ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1"]
+        );
+    });
+}
+
+#[test]
+fn persisted_uart_configuration_needs_no_recovery() {
+    let module = Module::new()
+        .respond("AT+UART_DEF", "\r\nOK\r\n")
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_uart(UartConfig::new(921_600), true, |_| Ok::<(), ()>(())).unwrap();
+
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        crash(&module);
+
+        let mut buffer = [0; 16];
+        assert!(network.read(&mut socket, &mut buffer).is_err());
+        assert_eq!(module.commands(), ["ATE0", "AT+CIPMUX=1", "AT+CIPRECVMODE=1"]);
+    });
+}
+
+#[test]
+fn fails_connect_interrupted_by_reset() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond_once("AT+CIPSTART", "\r\nready\r\n0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        assert!(matches!(
+            network.connect(socket, remote()),
+            Err(TcpError::SocketNotOpen)
+        ));
+
+        // the link was released
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.connect(socket, remote()).unwrap();
+        assert!(network.is_connected(&socket).unwrap());
+    });
+}
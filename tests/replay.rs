@@ -0,0 +1,241 @@
+//! Replays recorded AT sessions through the `Ingress` and checks the
+//! responses, notifications and events it emits.
+//!
+//! Traces live in `tests/traces/*.trace`, see `tests/traces/README.md`
+//! for the format.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use drogue_esp8266::event::Event;
+use drogue_esp8266::ingress::{Ingress, IngressBuffer};
+use drogue_esp8266::protocol::Response;
+use heapless::consts::{U16, U2};
+use heapless::spsc::Queue;
+
+/// Number of times the digest loop runs before an expectation is checked.
+const DIGEST_PASSES: usize = 16;
+
+/// Period of the simulated digest loop, which runs repeatedly during a gap.
+const DIGEST_PERIOD_MS: usize = 1;
+
+/// Serial receiver fed from the trace.
+#[derive(Clone, Default)]
+struct ReplayRx {
+    bytes: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl embedded_hal::serial::Read<u8> for ReplayRx {
+    type Error = ();
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.bytes
+            .borrow_mut()
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+/// Ways of feeding received octets into the `Ingress`.
+#[derive(Clone, Copy, Debug)]
+enum Feed {
+    /// One octet at a time through `isr`.
+    Isr,
+    /// As slices through `ingest`.
+    Ingest,
+    /// Through a small circular DMA buffer via `ingest_circular`.
+    Circular,
+}
+
+/// Size of the simulated circular DMA buffer.
+const DMA_LEN: usize = 7;
+
+enum Step {
+    /// Octets received from the board.
+    Receive(Vec<u8>),
+    /// Idle time on the line in milliseconds, during which the digest loop runs.
+    Gap(usize),
+    /// Expected response or notification, in its `Debug` format.
+    Expect(String),
+    /// Expected event, in its `Debug` format.
+    ExpectEvent(String),
+}
+
+fn unescape(line: &str, location: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => out.push(b'\r'),
+            Some('n') => out.push(b'\n'),
+            Some('0') => out.push(0),
+            Some('\\') => out.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let octet = u8::from_str_radix(&hex, 16)
+                    .unwrap_or_else(|_| panic!("{}: invalid escape \\x{}", location, hex));
+                out.push(octet);
+            }
+            other => panic!("{}: invalid escape \\{:?}", location, other),
+        }
+    }
+    out
+}
+
+fn load(path: &Path) -> Vec<(String, Step)> {
+    let text = fs::read_to_string(path).unwrap();
+    let mut steps = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let location = format!("{}:{}", path.display(), i + 1);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (kind, rest) = line.split_at(1);
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+        let step = match kind {
+            "<" => Step::Receive(unescape(rest, &location)),
+            "~" => Step::Gap(
+                rest.parse()
+                    .unwrap_or_else(|_| panic!("{}: invalid gap {:?}", location, rest)),
+            ),
+            "=" => Step::Expect(rest.to_string()),
+            "!" => Step::ExpectEvent(rest.to_string()),
+            _ => panic!("{}: unknown step {:?}", location, kind),
+        };
+        steps.push((location, step));
+    }
+    steps
+}
+
+fn replay(path: &Path, feed: Feed) {
+    let mut response_queue: Queue<Response, U2> = Queue::new();
+    let mut notification_queue: Queue<Response, U16> = Queue::new();
+    let (response_producer, mut response_consumer) = response_queue.split();
+    let (notification_producer, mut notification_consumer) = notification_queue.split();
+    let mut event_queue: Queue<Event, U16> = Queue::new();
+    let (event_producer, mut event_consumer) = event_queue.split();
+
+    let mut ingress_buffer = IngressBuffer::new();
+    let rx = ReplayRx::default();
+    let mut ingress = Ingress::new(
+        rx.clone(),
+        &mut ingress_buffer,
+        response_producer,
+        notification_producer,
+    )
+    .with_events(event_producer);
+
+    let mut dma_buffer = [0u8; DMA_LEN];
+    let mut dma_position = 0;
+
+    let mut emitted: VecDeque<String> = VecDeque::new();
+    let mut events: VecDeque<String> = VecDeque::new();
+    let mut digest = |ingress: &mut Ingress<ReplayRx>,
+                      emitted: &mut VecDeque<String>,
+                      events: &mut VecDeque<String>,
+                      passes: usize| {
+        for _ in 0..passes {
+            ingress.digest();
+            while let Some(response) = response_consumer.dequeue() {
+                emitted.push_back(format!("{:?}", response));
+            }
+            while let Some(notification) = notification_consumer.dequeue() {
+                emitted.push_back(format!("{:?}", notification));
+            }
+            while let Some(event) = event_consumer.dequeue() {
+                events.push_back(format!("{:?}", event));
+            }
+        }
+    };
+
+    for (location, step) in load(path) {
+        match step {
+            Step::Receive(bytes) => match feed {
+                Feed::Isr => {
+                    rx.bytes.borrow_mut().extend(bytes);
+                    ingress
+                        .isr()
+                        .unwrap_or_else(|n| panic!("{}: ingress dropped {} octets", location, n));
+                }
+                Feed::Ingest => {
+                    ingress
+                        .ingest(&bytes)
+                        .unwrap_or_else(|n| panic!("{}: ingress dropped {} octets", location, n));
+                }
+                Feed::Circular => {
+                    for octet in bytes {
+                        dma_buffer[dma_position] = octet;
+                        dma_position = (dma_position + 1) % DMA_LEN;
+                        ingress
+                            .ingest_circular(&dma_buffer, dma_position)
+                            .unwrap_or_else(|n| panic!("{}: ingress dropped {} octets", location, n));
+                    }
+                }
+            },
+            Step::Gap(ms) => {
+                // the digest loop keeps its pace while the line is idle, so
+                // short gaps may leave octets for after the next frame.
+                let passes = (ms / DIGEST_PERIOD_MS).max(1);
+                digest(&mut ingress, &mut emitted, &mut events, passes);
+            }
+            Step::Expect(expected) => {
+                // each pass parses at most one response, so give the digest
+                // loop enough passes to work through everything received.
+                digest(&mut ingress, &mut emitted, &mut events, DIGEST_PASSES);
+                match emitted.pop_front() {
+                    Some(actual) => assert_eq!(actual, expected, "{} ({:?})", location, feed),
+                    None => panic!("{} ({:?}): expected {}, nothing emitted", location, feed, expected),
+                }
+            }
+            Step::ExpectEvent(expected) => {
+                digest(&mut ingress, &mut emitted, &mut events, DIGEST_PASSES);
+                // events are only checked where a trace asks for them
+                match events.iter().position(|event| *event == expected) {
+                    Some(index) => {
+                        events.drain(..=index);
+                    }
+                    None => panic!(
+                        "{} ({:?}): expected event {}, got {:?}",
+                        location, feed, expected, events
+                    ),
+                }
+            }
+        }
+    }
+
+    digest(&mut ingress, &mut emitted, &mut events, DIGEST_PASSES);
+    assert!(
+        emitted.is_empty(),
+        "{} ({:?}): unexpected trailing output {:?}",
+        path.display(),
+        feed,
+        emitted
+    );
+}
+
+#[test]
+fn replay_traces() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/traces");
+    let mut traces: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(OsStr::new("trace")))
+        .collect();
+    traces.sort();
+    assert!(!traces.is_empty(), "no traces found in {}", dir.display());
+
+    for trace in traces {
+        for feed in &[Feed::Isr, Feed::Ingest, Feed::Circular] {
+            replay(&trace, *feed);
+        }
+    }
+}
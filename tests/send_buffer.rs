@@ -0,0 +1,92 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+
+#[test]
+fn pipelines_segments() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSENDBUF=0,5", "1,0\r\n\r\nOK\r\n> ")
+        .respond("AT+CIPSENDBUF=0,6", "2,0\r\n\r\nOK\r\n> ")
+        .respond("AT+CIPCHECKSEQ=0,1", "0,1,1\r\n\r\nOK\r\n")
+        .respond("AT+CIPCHECKSEQ=0,2", "0,2,0\r\n\r\nOK\r\n")
+        .respond("AT+CIPBUFSTATUS=0", "3,2,1,2033,1\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        assert_eq!(network.write_buffered(&mut socket, b"first").unwrap(), 1);
+        assert_eq!(network.write_buffered(&mut socket, b"second").unwrap(), 2);
+        module.send("0,1,SEND OK\r\n");
+
+        assert!(network.is_segment_sent(&socket, 1).unwrap());
+        assert!(!network.is_segment_sent(&socket, 2).unwrap());
+
+        let status = network.buffer_status(&socket).unwrap();
+        assert_eq!(status.next_segment_id, 3);
+        assert_eq!(status.acknowledged_segment_id, 1);
+        assert_eq!(status.queued, 1);
+        assert_eq!(network.statistics().links[0].bytes_sent, 11);
+        assert_eq!(module.data(), [b"first".to_vec(), b"second".to_vec()]);
+    });
+}
+
+#[test]
+fn reports_full_buffer_as_would_block() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSENDBUF", "\r\nERROR\r\n")
+        .respond("AT+CIPBUFSTATUS=0", "9,8,8,2,1\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        assert!(matches!(
+            network.write_buffered(&mut socket, b"data"),
+            Err(nb::Error::WouldBlock)
+        ));
+    });
+}
+
+#[test]
+fn reports_other_refusals_as_errors() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSENDBUF", "\r\nERROR\r\n")
+        .respond("AT+CIPBUFSTATUS=0", "9,8,8,2048,0\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        assert!(matches!(
+            network.write_buffered(&mut socket, b"data"),
+            Err(nb::Error::Other(TcpError::WriteError))
+        ));
+    });
+}
+
+#[test]
+fn reports_closed_link() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSENDBUF", "0,CLOSED\r\n\r\nERROR\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        assert!(matches!(
+            network.write_buffered(&mut socket, b"data"),
+            Err(nb::Error::Other(TcpError::SocketNotOpen))
+        ));
+        assert!(!module.commands().iter().any(|c| c.starts_with("AT+CIPBUFSTATUS")));
+    });
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{remote, run, Module};
+use core::fmt::Write;
+use drogue_esp8266::protocol::MAX_SEGMENT_LEN;
+use drogue_network::tcp::{Mode, TcpError, TcpStack};
+
+fn connected() -> Module {
+    Module::new()
+        .respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSENDEX", "\r\nOK\r\n> ")
+}
+
+#[test]
+fn streams_lines() {
+    let module = connected();
+
+    run(&module, |adapter| {
+        let mut network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        let path = "/";
+        let mut writer = socket.writer(&mut network);
+        write!(writer, "GET {} HTTP/1.0\r\n", path).unwrap();
+        writer.write_all(b"Host: drogue.io\r\n\r\n").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        assert_eq!(
+            module.data(),
+            [b"GET / HTTP/1.0\r\nHost: drogue.io\r\n\r\n".to_vec()]
+        );
+        assert_eq!(network.statistics().links[0].bytes_sent, 35);
+    });
+}
+
+#[test]
+fn escapes_terminator() {
+    let module = connected();
+
+    run(&module, |adapter| {
+        let mut network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        socket.writer(&mut network).write_all(b"C:\\0\\").unwrap();
+
+        assert_eq!(module.data(), [b"C:\\0\\".to_vec()]);
+    });
+}
+
+#[test]
+fn splits_long_data_into_chunks() {
+    let module = connected();
+
+    run(&module, |adapter| {
+        let mut network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        let data = vec![b'x'; MAX_SEGMENT_LEN + 10];
+        socket.writer(&mut network).write_all(&data).unwrap();
+
+        let chunks = module.data();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|chunk| chunk.len() < MAX_SEGMENT_LEN));
+        assert_eq!(chunks.concat(), data);
+    });
+}
+
+#[test]
+fn failed_writer_is_not_flushed() {
+    let module = connected();
+
+    run(&module, |adapter| {
+        let mut network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let mut socket = network.connect(socket, remote()).unwrap();
+
+        let mut writer = socket.writer(&mut network);
+        writer.write_all(b"GET").unwrap();
+        module.state.lock().unwrap().writable = Some(0);
+        assert!(matches!(writer.write_all(b" / HTTP/1.0"), Err(TcpError::WriteError)));
+
+        // the writer gives up, even once the wire works again
+        module.state.lock().unwrap().writable = None;
+        assert!(matches!(writer.write_all(b"\r\n"), Err(TcpError::WriteError)));
+        assert!(matches!(writer.flush(), Err(TcpError::WriteError)));
+        drop(writer);
+
+        assert!(module.data().is_empty());
+    });
+}
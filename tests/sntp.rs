@@ -0,0 +1,34 @@
+mod common;
+
+use common::{run, Module};
+use drogue_esp8266::adapter::AdapterError;
+use drogue_esp8266::protocol::DateTime;
+
+#[test]
+fn converts_time_to_utc() {
+    let module = Module::new()
+        .respond("AT+CIPSNTPCFG", "\r\nOK\r\n")
+        .respond("AT+CIPSNTPTIME?", "+CIPSNTPTIME:Mon Jan 01 01:30:00 2024\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_sntp_config(true, 2, &["pool.ntp.org"]).unwrap();
+        assert_eq!(
+            adapter.get_time().unwrap(),
+            DateTime { year: 2023, month: 12, day: 31, hour: 23, minute: 30, second: 0 }
+        );
+        assert_eq!(
+            module.commands()[4..],
+            ["AT+CIPSNTPCFG=1,2,\"pool.ntp.org\"", "AT+CIPSNTPTIME?"]
+        );
+    });
+}
+
+#[test]
+fn reports_unsynchronized_time() {
+    let module = Module::new()
+        .respond("AT+CIPSNTPTIME?", "+CIPSNTPTIME:Thu Jan 01 00:00:03 1970\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        assert!(matches!(adapter.get_time(), Err(AdapterError::TimeNotSynchronized)));
+    });
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_network::tcp::{Mode, TcpStack};
+
+#[test]
+fn connects_with_keep_alive() {
+    let module = Module::new().respond("AT+CIPSTART", "0,CONNECT\r\n\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let mut socket = network.open(Mode::NonBlocking).unwrap();
+        socket.set_keep_alive(Some(60));
+        let socket = network.connect(socket, remote()).unwrap();
+
+        assert_eq!(socket.keep_alive(), Some(60));
+        assert_eq!(
+            module.commands().last().unwrap(),
+            "AT+CIPSTART=0,\"TCP\",\"192.168.1.1\",8080,60"
+        );
+    });
+}
+
+#[test]
+fn rejects_invalid_keep_alive() {
+    let module = Module::new();
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let mut socket = network.open(Mode::NonBlocking).unwrap();
+        socket.set_keep_alive(Some(0));
+
+        assert!(network.connect(socket, remote()).is_err());
+        assert!(!module.commands().iter().any(|c| c.starts_with("AT+CIPSTART")));
+    });
+}
+
+#[test]
+fn configures_server_timeout() {
+    let module = Module::new()
+        .respond("AT+CIPSTO=", "\r\nOK\r\n")
+        .respond("AT+CIPSTO?", "+CIPSTO:180\r\n\r\nOK\r\n");
+
+    run(&module, |mut adapter| {
+        adapter.set_server_timeout(180).unwrap();
+        assert_eq!(adapter.get_server_timeout().unwrap(), 180);
+        assert!(adapter.set_server_timeout(7201).is_err());
+    });
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::{run, Module};
+use drogue_network::addr::{HostAddr, HostSocketAddr};
+use drogue_network::tcp::{Mode, TcpStack};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn counts_commands_errors_and_traffic() {
+    let module = Module::new()
+        .respond("AT+CIPSTART", "1,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPRECVDATA=1", "+CIPRECVDATA,5:hello\r\n\r\nOK\r\n")
+        .respond("AT+SLEEP", "\r\nERROR\r\n");
+
+    run(&module, |mut adapter| {
+        assert!(!adapter.send_raw("AT+SLEEP=1").unwrap().is_ok());
+
+        let network = adapter.into_network_stack();
+        let _unused = network.open(Mode::NonBlocking).unwrap();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let remote = HostSocketAddr::new(HostAddr::ipv4([192, 168, 1, 1]), 80);
+        let mut socket = network.connect(socket, remote).unwrap();
+
+        module.send("\r\n+IPD,1,5\r\n");
+        thread::sleep(Duration::from_millis(50));
+        let mut buffer = [0; 16];
+        assert_eq!(network.read(&mut socket, &mut buffer).unwrap(), 5);
+
+        let statistics = network.statistics();
+        assert_eq!(statistics.commands, 3);
+        assert_eq!(statistics.errors, 1);
+        assert_eq!(statistics.send_failures, 0);
+        assert_eq!(statistics.links[0].bytes_received, 0);
+        assert_eq!(statistics.links[1].bytes_received, 5);
+    });
+}
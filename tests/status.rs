@@ -0,0 +1,60 @@
+mod common;
+
+use common::{remote, run, Module};
+use drogue_esp8266::protocol::{ConnectionType, StationStatus};
+use drogue_network::addr::{IpAddr, Ipv4Addr, SocketAddr};
+use drogue_network::tcp::{Mode, TcpStack};
+
+#[test]
+fn reports_open_links() {
+    let module = Module::new().respond(
+        "AT+CIPSTATUS",
+        "STATUS:3\r\n\
+         +CIPSTATUS:0,\"TCP\",\"192.168.1.1\",8080,40000,0\r\n\
+         +CIPSTATUS:1,\"UDP\",\"192.168.1.2\",53,40001,0\r\n\
+         \r\nOK\r\n",
+    );
+
+    run(&module, |mut adapter| {
+        let status = adapter.query_connection_status().unwrap();
+        assert_eq!(status.status, StationStatus::Connected);
+        assert_eq!(status.links.len(), 2);
+
+        let link = status.link(1).unwrap();
+        assert_eq!(link.connection_type, ConnectionType::UDP);
+        assert_eq!(link.remote, SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), 53));
+        assert_eq!(link.local_port, 40001);
+        assert!(!link.server);
+        assert!(status.link(2).is_none());
+    });
+}
+
+#[test]
+fn reconciles_missed_notifications() {
+    let module = Module::new()
+        .respond("AT+CIPSTART=0", "0,CONNECT\r\n\r\nOK\r\n")
+        .respond("AT+CIPSTART=1", "1,CONNECT\r\n\r\nOK\r\n")
+        .respond(
+            "AT+CIPSTATUS",
+            "STATUS:3\r\n\
+             +CIPSTATUS:1,\"TCP\",\"192.168.1.1\",8080,40001,0\r\n\
+             +CIPSTATUS:3,\"TCP\",\"192.168.1.1\",8080,40003,0\r\n\
+             \r\nOK\r\n",
+        )
+        .respond("AT+CIPCLOSE", "\r\nOK\r\n");
+
+    run(&module, |adapter| {
+        let network = adapter.into_network_stack();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let first = network.connect(socket, remote()).unwrap();
+        let socket = network.open(Mode::NonBlocking).unwrap();
+        let second = network.connect(socket, remote()).unwrap();
+
+        // the module dropped link 0 and opened link 3 without telling
+        network.reconcile_sockets().unwrap();
+
+        assert!(!network.is_connected(&first).unwrap());
+        assert!(network.is_connected(&second).unwrap());
+        assert_eq!(module.commands().last().unwrap(), "AT+CIPCLOSE=3");
+    });
+}
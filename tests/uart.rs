@@ -0,0 +1,55 @@
+mod common;
+
+use common::{run, Module};
+use drogue_esp8266::adapter::AdapterError;
+use drogue_esp8266::protocol::UartConfig;
+
+fn commands_since_uart(module: &Module) -> Vec<String> {
+    let commands = module.commands();
+    let start = commands.iter().position(|c| c.starts_with("AT+UART_CUR")).unwrap();
+    commands[start..].to_vec()
+}
+
+#[test]
+fn retries_unanswered_test_command() {
+    let module = Module::new();
+
+    run(&module, |mut adapter| {
+        // the first command at the new baud rate is garbled, and not answered
+        let _ = module
+            .clone()
+            .respond_once("AT+UART_CUR", "\r\nOK\r\n")
+            .respond_once("AT", "");
+
+        let mut baud_rate = None;
+        adapter
+            .set_uart(UartConfig::new(921_600), false, |config| {
+                baud_rate.replace(config.baud_rate);
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        assert_eq!(baud_rate, Some(921_600));
+        assert_eq!(
+            commands_since_uart(&module),
+            ["AT+UART_CUR=921600,8,1,0,0", "AT", "AT"]
+        );
+    });
+}
+
+#[test]
+fn gives_up_on_silent_module() {
+    let module = Module::new();
+
+    run(&module, |mut adapter| {
+        let _ = module.clone().respond("AT", "").respond("AT+UART_CUR", "\r\nOK\r\n");
+
+        assert!(matches!(
+            adapter.set_uart(UartConfig::new(921_600), false, |_| Ok::<(), ()>(())),
+            Err(AdapterError::UnableToConfigure)
+        ));
+        assert_eq!(
+            commands_since_uart(&module),
+            ["AT+UART_CUR=921600,8,1,0,0", "AT", "AT", "AT"]
+        );
+    });
+}